@@ -1,20 +1,31 @@
-mod raytracing;
-
-use raytracing::{
-    camera::Camera,
+use inoneweekend::raytracing::{
+    camera::{Camera, Focus},
     color::Color,
     hittable::HittableList,
     materials::{Dielectric, Lambertian, Metal},
-    random_float, random_float_range,
+    random_float, random_float_range, seed_random,
     shapes::Sphere,
     vec3::{Point, Vec3},
 };
 
+use std::{env, process};
+
 // reading from this:
 // https://raytracing.github.io/books/RayTracingInOneWeekend.html
 
+// the random spheres have to come out the same for a checkpoint to be resumed
+const SCENE_SEED: u64 = 42;
+const CHECKPOINT: &str = "render.checkpoint";
+
+// inoneweekend                       render from scratch
+// inoneweekend resume <checkpoint>   carry on an interrupted render
+// inoneweekend more <checkpoint> <samples_per_pixel>
+//                                    refine a finished render to more samples
 fn main() {
-    let mut world = HittableList { objects: vec![] };
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    seed_random(SCENE_SEED);
+    let mut world = HittableList::default();
 
     let ground_material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
     world.add(Box::new(Sphere::new(
@@ -104,7 +115,42 @@ fn main() {
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
 
     camera.defocus_angle = 0.6;
-    camera.focus_dist = 10.0;
+    camera.focus = Focus::LookAt;
 
-    camera.render(world);
+    camera.checkpoint = Some(CHECKPOINT.to_string());
+
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {
+            camera.render(world);
+            Ok(())
+        }
+        ["resume", checkpoint] => {
+            camera.checkpoint = Some(checkpoint.to_string());
+            camera.resume(world, checkpoint)
+        }
+        ["more", checkpoint, samples_per_pixel] => {
+            let Ok(samples_per_pixel) = samples_per_pixel.parse() else {
+                eprintln!("samples per pixel should be a number, not {samples_per_pixel}");
+                process::exit(2);
+            };
+            camera.samples_per_pixel = samples_per_pixel;
+            // adaptive renders stop at their own max instead
+            if let Some(adaptive) = &mut camera.adaptive {
+                adaptive.max_samples = samples_per_pixel.max(adaptive.min_samples);
+            }
+            camera.checkpoint = Some(checkpoint.to_string());
+            camera.resume(world, checkpoint)
+        }
+        _ => {
+            eprintln!(
+                "usage: inoneweekend [resume <checkpoint> | more <checkpoint> <samples_per_pixel>]"
+            );
+            process::exit(2);
+        }
+    };
+
+    if let Err(error) = result {
+        eprintln!("couldn't resume: {error}");
+        process::exit(1);
+    }
 }
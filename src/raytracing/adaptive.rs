@@ -0,0 +1,139 @@
+// stops sampling a pixel once the 95% confidence interval of its mean luminance is
+// within noise_threshold of the mean, after at least min_samples and at most max_samples
+#[derive(Clone, Copy)]
+pub struct Adaptive {
+    pub noise_threshold: f64,
+    pub min_samples: u32,
+    pub max_samples: u32,
+}
+
+// running mean and variance of a pixel's samples (Welford 1962)
+#[derive(Clone, Copy, Default)]
+pub struct Welford {
+    pub count: u32,
+    pub mean: f64,
+    pub(super) m2: f64,
+}
+
+impl Adaptive {
+    pub fn new(noise_threshold: f64, min_samples: u32, max_samples: u32) -> Self {
+        let min_samples = min_samples.max(2);
+        Self {
+            noise_threshold,
+            min_samples,
+            max_samples: max_samples.max(min_samples),
+        }
+    }
+
+    pub fn converged(&self, stats: &Welford) -> bool {
+        if stats.count < self.min_samples {
+            return false;
+        }
+        if stats.count >= self.max_samples {
+            return true;
+        }
+
+        let half_width = 1.96 * (stats.variance() / stats.count as f64).sqrt();
+        // relative to the mean, but near black pixels would chase it forever
+        half_width <= self.noise_threshold * stats.mean.max(0.01)
+    }
+}
+
+impl Welford {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // Chan et al., the stats of both sets of samples together
+    pub fn merge(&mut self, other: &Welford) {
+        if other.count == 0 {
+            return;
+        }
+
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        let weight = other.count as f64 / count as f64;
+        self.mean += delta * weight;
+        self.m2 += other.m2 + delta * delta * self.count as f64 * weight;
+        self.count = count;
+    }
+
+    // unbiased sample variance
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            self.m2 / (self.count - 1) as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Vec<f64> {
+        (0..100)
+            .map(|i| ((i * 37 % 101) as f64).sqrt() + 1e3)
+            .collect()
+    }
+
+    fn welford(values: &[f64]) -> Welford {
+        let mut stats = Welford::default();
+        for &value in values {
+            stats.add(value);
+        }
+        stats
+    }
+
+    #[test]
+    fn matches_two_pass_variance() {
+        let values = values();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance =
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+
+        let stats = welford(&values);
+        assert_eq!(stats.count, 100);
+        assert!((stats.mean - mean).abs() < 1e-9);
+        assert!((stats.variance() - variance).abs() < 1e-9);
+    }
+
+    #[test]
+    fn merge_matches_sequential() {
+        let values = values();
+        let sequential = welford(&values);
+
+        for split in [0, 1, 37, 99, 100] {
+            let mut merged = welford(&values[..split]);
+            merged.merge(&welford(&values[split..]));
+
+            assert_eq!(merged.count, sequential.count);
+            assert!((merged.mean - sequential.mean).abs() < 1e-9);
+            assert!((merged.variance() - sequential.variance()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn converges_between_min_and_max() {
+        let adaptive = Adaptive::new(0.01, 4, 16);
+        let mut flat = Welford::default();
+        for _ in 0..3 {
+            flat.add(0.5);
+        }
+        assert!(!adaptive.converged(&flat));
+        flat.add(0.5);
+        assert!(adaptive.converged(&flat));
+
+        let mut noisy = Welford::default();
+        for i in 0..15 {
+            noisy.add((i % 2) as f64);
+        }
+        assert!(!adaptive.converged(&noisy));
+        noisy.add(0.0);
+        assert!(adaptive.converged(&noisy));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{io, path::Path};
+
+use super::{
+    degrees_to_radians,
+    distribution::Distribution2D,
+    hdr::invalid_data,
+    image::Image,
+    random_float,
+    vec3::{random_in_unit_disk, Vec3},
+    PI,
+};
+
+// shape of the lens opening, which is the shape out of focus highlights take.
+// samples are points in the unit disk (or within the [-1, 1] square for images)
+pub enum Aperture {
+    Circular,
+    // regular polygon inscribed in the unit circle, rotation in degrees
+    Polygonal { blades: u32, rotation: f64 },
+    Image(ImageAperture),
+}
+
+impl Aperture {
+    pub fn polygonal(blades: u32, rotation: f64) -> Self {
+        Aperture::Polygonal {
+            blades: blades.max(3),
+            rotation,
+        }
+    }
+
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circular => random_in_unit_disk(),
+            Aperture::Polygonal { blades, rotation } => {
+                // every blade edge makes an equal triangle with the center
+                let edge = ((random_float() * *blades as f64) as u32).min(blades - 1);
+                let step = 2.0 * PI / *blades as f64;
+                let start = degrees_to_radians(*rotation) + edge as f64 * step;
+
+                let a = Vec3::new(start.cos(), start.sin(), 0.0);
+                let b = Vec3::new((start + step).cos(), (start + step).sin(), 0.0);
+
+                // uniform point in the triangle, the center being the third corner
+                let (mut s, mut t) = (random_float(), random_float());
+                if s + t > 1.0 {
+                    (s, t) = (1.0 - s, 1.0 - t);
+                }
+                (a * s) + (b * t)
+            }
+            Aperture::Image(image) => image.sample(),
+        }
+    }
+}
+
+// any grayscale image as the aperture, brighter pixels let more light through. the
+// longer side spans [-1, 1] and the shorter keeps the image's aspect ratio
+pub struct ImageAperture {
+    distribution: Distribution2D,
+    half_width: f64,
+    half_height: f64,
+}
+
+impl ImageAperture {
+    pub fn new(image: &Image) -> io::Result<Self> {
+        if image.width == 0 || image.height == 0 {
+            return Err(invalid_data("aperture image is empty"));
+        }
+
+        let weights: Vec<f64> = image.pixels.iter().map(|p| p.luminance()).collect();
+        let longest = image.width.max(image.height) as f64;
+        Ok(Self {
+            distribution: Distribution2D::new(&weights, image.width, image.height),
+            half_width: image.width as f64 / longest,
+            half_height: image.height as f64 / longest,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(&Image::load(path, false)?)
+    }
+
+    fn sample(&self) -> Vec3 {
+        let (u, v, _pdf) = self.distribution.sample(random_float(), random_float());
+        Vec3::new(
+            (2.0 * u - 1.0) * self.half_width,
+            (1.0 - 2.0 * v) * self.half_height,
+            0.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::{color::Color, seed_random};
+
+    #[test]
+    fn empty_image_is_an_error() {
+        assert!(ImageAperture::new(&Image::new(0, 0)).is_err());
+        assert!(ImageAperture::new(&Image::new(3, 0)).is_err());
+    }
+
+    #[test]
+    fn wide_image_keeps_its_aspect_ratio() {
+        seed_random(1);
+        let mut image = Image::new(4, 1);
+        for pixel in image.pixels.iter_mut() {
+            *pixel = Color::new(1.0, 1.0, 1.0);
+        }
+        let aperture = ImageAperture::new(&image).unwrap();
+
+        let (mut widest, mut tallest) = (0.0f64, 0.0f64);
+        for _ in 0..10_000 {
+            let p = aperture.sample();
+            widest = widest.max(p.x.abs());
+            tallest = tallest.max(p.y.abs());
+        }
+        assert!(widest > 0.99 && widest <= 1.0);
+        assert!(tallest > 0.24 && tallest <= 0.25);
+    }
+}
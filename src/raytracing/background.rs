@@ -0,0 +1,158 @@
+use std::{io, path::Path};
+
+use super::{
+    color::Color, degrees_to_radians, distribution::Distribution2D, hdr, image::Image,
+    random_float, vec3::Vec3, PI,
+};
+
+pub struct BackgroundSample {
+    pub direction: Vec3,
+    pub radiance: Color,
+    pub pdf: f64,
+}
+
+// what a ray sees when it escapes the scene
+pub trait Background: Send + Sync {
+    fn color(&self, direction: Vec3) -> Color;
+
+    // backgrounds that can't be importance sampled are only found by bsdf sampling
+    fn sample(&self) -> Option<BackgroundSample> {
+        None
+    }
+
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
+}
+
+pub struct Gradient {
+    pub horizon: Color,
+    pub zenith: Color,
+}
+
+impl Gradient {
+    pub fn new(horizon: Color, zenith: Color) -> Self {
+        Self { horizon, zenith }
+    }
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Background for Gradient {
+    fn color(&self, direction: Vec3) -> Color {
+        let unit_direction = direction.unit_vector();
+        let a = 0.5 * (unit_direction.y + 1.0);
+
+        (self.horizon * (1.0 - a)) + (self.zenith * a)
+    }
+}
+
+// equirectangular environment map, +y is up and the centre of the image looks down -z
+pub struct EnvironmentMap {
+    image: Image,
+    distribution: Distribution2D,
+    pub rotation: f64,
+    pub intensity: f64,
+}
+
+impl EnvironmentMap {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(hdr::load(path)?))
+    }
+
+    pub fn new(image: Image) -> Self {
+        // weight each texel by sin(theta) so the poles aren't oversampled
+        let mut luminance = Vec::with_capacity(image.width * image.height);
+        for y in 0..image.height {
+            let sin_theta = (PI * (y as f64 + 0.5) / image.height as f64).sin();
+            for x in 0..image.width {
+                luminance.push(image.get(x, y).luminance() * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&luminance, image.width, image.height);
+
+        Self {
+            image,
+            distribution,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    // rotation is in degrees around the up axis
+    pub fn with_rotation(mut self, rotation: f64) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = direction.unit_vector();
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        let phi = d.x.atan2(-d.z) + PI - degrees_to_radians(self.rotation);
+
+        let u = (phi / (2.0 * PI)).rem_euclid(1.0);
+        let v = theta / PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let theta = v * PI;
+        let phi = u * 2.0 * PI + degrees_to_radians(self.rotation) - PI;
+
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let x = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+        self.image.get(x, y) * self.intensity
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn color(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        self.lookup(u, v)
+    }
+
+    fn sample(&self) -> Option<BackgroundSample> {
+        let (u, v, map_pdf) = self.distribution.sample(random_float(), random_float());
+        if map_pdf == 0.0 {
+            return None;
+        }
+
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return None;
+        }
+
+        Some(BackgroundSample {
+            direction: self.uv_to_direction(u, v),
+            radiance: self.lookup(u, v),
+            pdf: map_pdf / (2.0 * PI * PI * sin_theta),
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
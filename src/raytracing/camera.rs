@@ -1,27 +1,266 @@
+use crate::raytracing::adaptive::{Adaptive, Welford};
+use crate::raytracing::aperture::Aperture;
+use crate::raytracing::background::{Background, Gradient};
 use crate::raytracing::color::Color;
 use crate::raytracing::degrees_to_radians;
+use crate::raytracing::lens::LensSystem;
 use crate::raytracing::ray::Ray;
-use crate::raytracing::vec3::{cross, Point, Vec3};
+use crate::raytracing::vec3::{cross, dot, Point, Vec3};
 
-use super::hittable::HittableList;
-use super::random_float;
-use super::vec3::random_in_unit_disk;
+use super::film::{CheckpointSettings, Film, Filter};
+use super::hdr::invalid_data;
+use super::hittable::{HitRecord, HittableList};
+use super::image::Image;
+use super::interval::Interval;
+use super::sampler::{self, Independent, SampleIndex, Sampler};
+use super::spectrum::SampledWavelengths;
+use super::{random_float, PI};
+
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+// full frame 36x24mm, used to turn vfov into a focal length
+const SENSOR_HEIGHT_MM: f64 = 24.0;
+
+// pixels along each side of the squares the image is split into for the worker threads
+const TILE_SIZE: u32 = 32;
+
+// exposure settings of a real camera. scene radiance is taken to be in cd/m^2
+#[derive(Clone, Copy)]
+pub struct Exposure {
+    pub f_number: f64,
+    // in seconds
+    pub shutter_speed: f64,
+    pub iso: f64,
+    // how big a scene unit is, the aperture's size in the scene depends on it
+    pub meters_per_unit: f64,
+}
+
+impl Exposure {
+    pub fn new(f_number: f64, shutter_speed: f64, iso: f64) -> Self {
+        Self {
+            f_number,
+            shutter_speed,
+            iso,
+            meters_per_unit: 1.0,
+        }
+    }
+
+    pub fn with_meters_per_unit(mut self, meters_per_unit: f64) -> Self {
+        self.meters_per_unit = meters_per_unit;
+        self
+    }
+
+    pub fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    // saturation based sensitivity, the luminance that just clips the sensor maps to 1
+    pub fn scale(&self) -> f64 {
+        1.0 / (1.2 * 2.0_f64.powf(self.ev100()))
+    }
+
+    // the cone the aperture subtends from the focus point, in degrees
+    fn defocus_angle(&self, vfov: f64, focus_dist: f64) -> f64 {
+        let focal_length_mm = SENSOR_HEIGHT_MM / 2.0 / (degrees_to_radians(vfov) / 2.0).tan();
+        let aperture = focal_length_mm / self.f_number / 1000.0 / self.meters_per_unit;
+
+        2.0 * (aperture / 2.0 / focus_dist).atan() * (180.0 / PI)
+    }
+}
+
+// Brown-Conrady lens distortion with the same coefficients opencv calibrates, in
+// normalized image coordinates (x right, y down, divided by the focal length)
+#[derive(Clone, Copy, Default)]
+pub struct Distortion {
+    pub k1: f64,
+    pub k2: f64,
+    pub k3: f64,
+    pub p1: f64,
+    pub p2: f64,
+}
+
+impl Distortion {
+    pub fn new(k1: f64, k2: f64, k3: f64, p1: f64, p2: f64) -> Self {
+        Self { k1, k2, k3, p1, p2 }
+    }
+
+    pub fn radial(k1: f64, k2: f64, k3: f64) -> Self {
+        Self::new(k1, k2, k3, 0.0, 0.0)
+    }
+
+    // where the lens puts an undistorted point
+    pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+
+    // the model has no closed form inverse, so solve for it with newton's method.
+    // past the point where strong distortion folds back over itself there is no
+    // inverse at all, and the closest point found is used
+    pub fn undistort(&self, x: f64, y: f64) -> (f64, f64) {
+        const TOLERANCE: f64 = 1e-10;
+
+        let (mut ux, mut uy) = (x, y);
+        let mut best = (ux, uy);
+        let mut best_error = f64::INFINITY;
+
+        for _ in 0..20 {
+            let (dx, dy) = self.distort(ux, uy);
+            let (ex, ey) = (x - dx, y - dy);
+            let error = ex.hypot(ey);
+            if error < best_error {
+                best = (ux, uy);
+                best_error = error;
+            }
+            if error < TOLERANCE {
+                break;
+            }
+
+            let [[a, b], [c, d]] = self.jacobian(ux, uy);
+            let determinant = a * d - b * c;
+            if determinant.abs() < 1e-12 {
+                break;
+            }
+            ux += (d * ex - b * ey) / determinant;
+            uy += (a * ey - c * ex) / determinant;
+        }
+        best
+    }
+
+    // derivatives of distort, rows are the output x and y
+    fn jacobian(&self, x: f64, y: f64) -> [[f64; 2]; 2] {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        let radial_dr2 = self.k1 + r2 * (2.0 * self.k2 + 3.0 * r2 * self.k3);
+        let cross = 2.0 * x * y * radial_dr2 + 2.0 * self.p1 * x + 2.0 * self.p2 * y;
+
+        [
+            [
+                radial + 2.0 * x * x * radial_dr2 + 2.0 * self.p1 * y + 6.0 * self.p2 * x,
+                cross,
+            ],
+            [
+                cross,
+                radial + 2.0 * y * y * radial_dr2 + 6.0 * self.p1 * y + 2.0 * self.p2 * x,
+            ],
+        ]
+    }
+}
+
+// how focus_dist is worked out when the camera is initialized
+pub enum Focus {
+    // focus_dist is used as it is
+    Manual,
+    // the plane through lookat
+    LookAt,
+    // the center of an object added with HittableList::add_named
+    Object(String),
+    // autofocus on whatever is under pixel (x, y)
+    Probe { x: u32, y: u32 },
+}
+
+#[derive(Clone, Copy)]
+pub enum Projection {
+    // pinhole or thin lens, with the field of view set by vfov
+    Perspective,
+    // parallel rays, view_height is the height of the image plane in scene units
+    // and the width follows from the aspect ratio
+    Orthographic { view_height: f64 },
+    // full sphere, longitude across and latitude down, centered on lookat
+    Equirectangular,
+    // omni-directional stereo, left eye in the top half and right eye in the bottom
+    StereoEquirectangular { interocular_distance: f64 },
+    // circular image, fov in degrees across the diameter
+    Fisheye { mapping: FisheyeMapping, fov: f64 },
+    // six square faces in a row, +x -x +y -y +z -z of the camera's u, v, w frame
+    // using the opengl face orientations
+    CubeMap,
+}
+
+#[derive(Clone, Copy)]
+pub enum FisheyeMapping {
+    // distance from the center is proportional to the angle
+    Equidistant,
+    // equal areas on the image cover equal solid angles
+    Equisolid,
+}
+
+impl Projection {
+    // panoramas fix the shape of the image, the others use the camera's aspect ratio
+    fn aspect_ratio(&self) -> Option<f64> {
+        match self {
+            Projection::Perspective | Projection::Orthographic { .. } => None,
+            Projection::Equirectangular => Some(2.0),
+            Projection::StereoEquirectangular { .. } => Some(1.0),
+            Projection::Fisheye { .. } => Some(1.0),
+            Projection::CubeMap => Some(6.0),
+        }
+    }
+}
 
 pub struct Camera {
     pub aspect_ratio: f64,
     pub samples_per_pixel: u32,
+    // takes samples per pixel until it converges instead, samples_per_pixel is ignored
+    pub adaptive: Option<Adaptive>,
     pub max_depth: u32,
     pub image_width: u32,
 
+    pub projection: Projection,
     pub vfov: f64,
     pub lookfrom: Point,
     pub lookat: Point,
     pub vup: Vec3,
 
+    // defocus_angle is worked out from the f-number when this is set
+    pub exposure: Option<Exposure>,
+    pub focus: Focus,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    pub aperture: Aperture,
+    // how far the lens barrel clips the aperture towards the frame edges, which
+    // squashes bokeh into cat's eyes. 0 turns it off, 1 clips the corners to nothing
+    pub cat_eye: f64,
+    // slides the image plane along u and v, in scene units on the focus plane
+    pub lens_shift: (f64, f64),
+    // rotates the plane of focus around u (tilt) and v (swing), in degrees
+    pub tilt: f64,
+    pub swing: f64,
+    // only applies to the perspective projection
+    pub distortion: Option<Distortion>,
+    // traces perspective rays through real lens elements instead of the thin lens.
+    // its focal length sets the field of view and its aperture the depth of field
+    pub lens: Option<LensSystem>,
+
+    // where random numbers come from while rendering
+    pub sampler: Box<dyn Sampler>,
+    // the same seed renders the same noise
+    pub seed: u64,
+    // how samples are weighted into the pixels around them
+    pub filter: Filter,
+    // worker threads rendering tiles, 0 uses every core
+    pub threads: usize,
+    // render also writes a ppm of how many samples each pixel took here
+    pub heatmap: Option<String>,
+    // samples per pixel added by each pass of the progressive render
+    pub pass_samples: u32,
+    // where to save the film between passes so the render can be resumed, at most
+    // once every checkpoint_interval seconds and always after the last pass
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: f64,
 
-    pixel_samples_scale: f64,
+    pub background: Box<dyn Background>,
+
+    exposure_scale: f64,
     image_height: u32,
     camera_center: Point,
     pixel_delta_u: Vec3,
@@ -33,6 +272,7 @@ pub struct Camera {
 
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    focus_plane_normal: Vec3,
 }
 
 impl Default for Camera {
@@ -47,7 +287,7 @@ impl Default for Camera {
         let lookat = Point::new(0.0, 0.0, 0.0);
         let vup = Point::new(0.0, 0.0, 0.0);
 
-        let pixel_samples_scale = 0.0;
+        let exposure_scale = 1.0;
         let image_height = 0;
         let pixel_delta_u = Vec3::new(0.0, 0.0, 0.0);
         let pixel_delta_v = Vec3::new(0.0, 0.0, 0.0);
@@ -65,13 +305,15 @@ impl Default for Camera {
             aspect_ratio,
             image_width,
             samples_per_pixel,
+            adaptive: None,
             max_depth,
+            projection: Projection::Perspective,
             vfov,
             lookfrom,
             lookat,
             vup,
             image_height,
-            pixel_samples_scale,
+            exposure_scale,
             camera_center: lookfrom,
             pixel00_loc,
             pixel_delta_u,
@@ -79,28 +321,66 @@ impl Default for Camera {
             u,
             v,
             w,
+            exposure: None,
+            focus: Focus::Manual,
             defocus_angle,
             focus_dist,
+            aperture: Aperture::Circular,
+            cat_eye: 0.0,
+            lens_shift: (0.0, 0.0),
+            tilt: 0.0,
+            swing: 0.0,
+            distortion: None,
+            lens: None,
+            focus_plane_normal: Vec3::new(0.0, 0.0, 0.0),
+            sampler: Box::new(Independent::default()),
+            seed: 0,
+            filter: Filter::default(),
+            threads: 0,
+            heatmap: None,
+            pass_samples: 16,
+            checkpoint: None,
+            checkpoint_interval: 60.0,
             defocus_disk_u,
             defocus_disk_v,
+            background: Box::new(Gradient::default()),
         }
     }
 }
 
 impl Camera {
-    pub fn initialize(&mut self) {
-        self.image_height = (self.image_width as f64 / self.aspect_ratio).round() as u32;
+    pub fn initialize(&mut self, world: &HittableList) {
+        let aspect_ratio = self.projection.aspect_ratio().unwrap_or(self.aspect_ratio);
+        self.image_height = (self.image_width as f64 / aspect_ratio).round() as u32;
         if self.image_height < 1 {
             self.image_height = 1;
         }
 
-        self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
-
         self.camera_center = self.lookfrom;
 
+        self.w = (self.lookfrom - self.lookat).unit_vector();
+        self.u = cross(self.vup, self.w).unit_vector();
+        self.v = cross(self.w, self.u);
+
         let theta = degrees_to_radians(self.vfov);
         let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * self.focus_dist;
+
+        match self.resolve_focus(world, h) {
+            Some(focus_dist) => self.focus_dist = focus_dist,
+            None => eprintln!("couldn't resolve focus, keeping focus_dist"),
+        }
+
+        self.exposure_scale = 1.0;
+        if let Some(exposure) = self.exposure {
+            self.exposure_scale = exposure.scale();
+            self.defocus_angle = exposure.defocus_angle(self.vfov, self.focus_dist);
+        }
+
+        let viewport_height = match self.projection {
+            Projection::Orthographic { view_height } => view_height,
+            // panoramas don't use the viewport
+            _ => 2.0 * h * self.focus_dist,
+        };
         let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
 
         eprintln!("lookfrom: {}", self.lookfrom);
@@ -110,28 +390,37 @@ impl Camera {
         eprintln!("theta: {theta}");
         eprintln!("h: {h}");
 
-        self.w = (self.lookfrom - self.lookat).unit_vector();
-        self.u = cross(self.vup, self.w).unit_vector();
-        self.v = cross(self.w, self.u);
-
         let viewport_u = self.u * viewport_width;
         let viewport_v = self.v * viewport_height * -1.0;
 
         self.pixel_delta_u = viewport_u / self.image_width as f64;
         self.pixel_delta_v = viewport_v / self.image_height as f64;
 
-        let viewport_upper_left = self.camera_center
-            - (self.w * self.focus_dist)
+        let viewport_upper_left = self.camera_center - (self.w * self.focus_dist)
+            + (self.u * self.lens_shift.0)
+            + (self.v * self.lens_shift.1)
             - (viewport_u / 2.0)
             - (viewport_v / 2.0);
 
         self.pixel00_loc = viewport_upper_left + ((self.pixel_delta_u + self.pixel_delta_v) * 0.5);
 
+        if let Some(lens) = &mut self.lens {
+            let film_width =
+                SENSOR_HEIGHT_MM * (self.image_width as f64 / self.image_height as f64);
+            let distance = self.focus_dist * lens.meters_per_unit * 1000.0;
+            lens.focus(distance, film_width, SENSOR_HEIGHT_MM);
+        }
+
+        self.focus_plane_normal = (self.w
+            + (self.v * degrees_to_radians(self.tilt).tan())
+            + (self.u * degrees_to_radians(self.swing).tan()))
+        .unit_vector();
+
         let defocus_radius = self.focus_dist * degrees_to_radians(self.defocus_angle / 2.0).tan();
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
 
-        eprintln!("aspect ratio: {}", self.aspect_ratio);
+        eprintln!("aspect ratio: {}", aspect_ratio);
         eprintln!("image_height: {}", self.image_height);
         eprintln!("image_width: {}", self.image_width);
 
@@ -156,24 +445,233 @@ impl Camera {
         eprintln!("defocus_disk_v: {}", self.defocus_disk_v);
     }
 
-    pub fn get_ray_for_pixel(&self, x: f64, y: f64) -> Ray {
-        let offset = Self::sample_square();
-        let pixel_sample = self.pixel00_loc
-            + (self.pixel_delta_u * (offset.x + x))
-            + (self.pixel_delta_v * (offset.y + y));
+    // x and y are continuous image coordinates, pixel (i, j) covers [i, i + 1) x [j, j + 1).
+    // None where the point isn't covered by the projection, like the corners of a fisheye
+    pub fn get_ray(&self, x: f64, y: f64) -> Option<Ray> {
+        if let Some(aspect_ratio) = self.projection.aspect_ratio() {
+            // the image is square cells of the unit square here, lenses don't apply
+            let s = x / self.image_width as f64;
+            let t = y / self.image_height as f64;
+            let (origin, direction) = self.panoramic_ray(s * aspect_ratio, t)?;
+
+            return Some(Ray {
+                origin,
+                direction,
+                wavelengths: SampledWavelengths::sample_visible(random_float()),
+            });
+        }
+
+        if let (Projection::Perspective, Some(lens)) = (self.projection, &self.lens) {
+            return self.lens_ray(lens, x, y);
+        }
+
+        let mut pixel_sample =
+            self.pixel00_loc + (self.pixel_delta_u * (x - 0.5)) + (self.pixel_delta_v * (y - 0.5));
 
         // let pixel_center = self.pixel00_loc + (self.pixel_delta_u * x) + (self.pixel_delta_v * y);
-        let ray_origin: Point = if self.defocus_angle <= 0.0 {
-            self.camera_center
+        let lens_offset = if self.defocus_angle <= 0.0 {
+            Vec3::new(0.0, 0.0, 0.0)
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(x, y)?
         };
-        let ray_direction = pixel_sample - ray_origin;
 
-        Ray {
+        // the ray through the middle of the lens, every lens sample meets it on the focus plane
+        let plane_center = self.camera_center - (self.w * self.focus_dist);
+        let (lens_center, chief_direction) = match self.projection {
+            // each pixel gets its own lens, on the plane through the camera center
+            Projection::Orthographic { .. } => {
+                let lens_center = pixel_sample + (self.w * self.focus_dist);
+                (lens_center, pixel_sample - lens_center)
+            }
+            _ => {
+                if let Some(distortion) = self.distortion {
+                    let offset = pixel_sample - plane_center;
+                    let (x, y) = distortion.undistort(
+                        dot(offset, self.u) / self.focus_dist,
+                        -dot(offset, self.v) / self.focus_dist,
+                    );
+                    pixel_sample = plane_center
+                        + (self.u * (x * self.focus_dist))
+                        + (self.v * (-y * self.focus_dist));
+                }
+                (self.camera_center, pixel_sample - self.camera_center)
+            }
+        };
+
+        let ray_origin: Point = lens_center + lens_offset;
+        let ray_direction = match self.focus_point(lens_center, chief_direction, plane_center) {
+            Some(focus_point) => focus_point - ray_origin,
+            // the chief ray runs parallel to a tilted plane, it's focused at infinity
+            None => chief_direction,
+        };
+
+        Some(Ray {
             origin: ray_origin,
             direction: ray_direction,
+            wavelengths: SampledWavelengths::sample_visible(random_float()),
+        })
+    }
+
+    // distance from the camera to the focus plane along the view direction,
+    // h is the tangent of half the vertical field of view
+    fn resolve_focus(&self, world: &HittableList, h: f64) -> Option<f64> {
+        let forward = self.w * -1.0;
+        let depth =
+            |point: Point| Some(dot(point - self.camera_center, forward)).filter(|d| *d > 0.0);
+
+        match &self.focus {
+            Focus::Manual => Some(self.focus_dist),
+            Focus::LookAt => depth(self.lookat),
+            Focus::Object(name) => depth(world.get(name)?.center()?),
+            Focus::Probe { x, y } => {
+                let s = (*x as f64 + 0.5) / self.image_width as f64 - 0.5;
+                let t = 0.5 - (*y as f64 + 0.5) / self.image_height as f64;
+                let aspect = self.image_width as f64 / self.image_height as f64;
+
+                let (origin, direction) = match self.projection {
+                    Projection::Orthographic { view_height } => (
+                        self.camera_center
+                            + (self.u * (s * view_height * aspect))
+                            + (self.v * (t * view_height)),
+                        forward,
+                    ),
+                    _ => (
+                        self.camera_center,
+                        forward + (self.u * (2.0 * h * aspect * s)) + (self.v * (2.0 * h * t)),
+                    ),
+                };
+                let probe = Ray {
+                    origin,
+                    direction,
+                    wavelengths: SampledWavelengths::default(),
+                };
+
+                let mut hit_record = HitRecord::default();
+                if !world.hit(
+                    &probe,
+                    Interval {
+                        min: 0.001,
+                        max: f64::INFINITY,
+                    },
+                    &mut hit_record,
+                ) {
+                    return None;
+                }
+                depth(hit_record.point)
+            }
+        }
+    }
+
+    // x and y are in pixels from the top left
+    fn lens_ray(&self, lens: &LensSystem, x: f64, y: f64) -> Option<Ray> {
+        let film_width = SENSOR_HEIGHT_MM * (self.image_width as f64 / self.image_height as f64);
+        let s = x / self.image_width as f64 - 0.5;
+        let t = y / self.image_height as f64 - 0.5;
+
+        // the lens flips the image, so the top left pixel is at the bottom right of the film
+        let (origin, direction, weight) =
+            lens.sample_ray((-s * film_width, t * SENSOR_HEIGHT_MM))?;
+
+        // vignetting as a survival probability, the weight is 1 in the middle of the frame
+        if random_float() >= weight {
+            return None;
+        }
+
+        let scale = 0.001 / lens.meters_per_unit;
+        let to_world = |p: Vec3| (self.u * p.x) + (self.v * p.y) - (self.w * p.z);
+        Some(Ray {
+            origin: self.camera_center + to_world(origin) * scale,
+            direction: to_world(direction),
+            wavelengths: SampledWavelengths::sample_visible(random_float()),
+        })
+    }
+
+    // where the chief ray crosses the (possibly tilted) plane of focus
+    fn focus_point(&self, origin: Point, direction: Vec3, plane_center: Point) -> Option<Point> {
+        let denominator = dot(direction, self.focus_plane_normal);
+        if denominator.abs() < 1e-9 {
+            return None;
         }
+
+        let t = dot(plane_center - origin, self.focus_plane_normal) / denominator;
+        if t <= 0.0 {
+            return None;
+        }
+        Some(origin + (direction * t))
+    }
+
+    // s runs from 0 to the aspect ratio across the image and t from 0 to 1 down it
+    fn panoramic_ray(&self, s: f64, t: f64) -> Option<(Point, Vec3)> {
+        let forward = self.w * -1.0;
+
+        match self.projection {
+            Projection::Equirectangular => {
+                let direction = self.spherical_direction(s / 2.0, t);
+                Some((self.camera_center, direction))
+            }
+            Projection::StereoEquirectangular {
+                interocular_distance,
+            } => {
+                let (eye, t) = if t < 0.5 {
+                    (-1.0, t * 2.0)
+                } else {
+                    (1.0, t * 2.0 - 1.0)
+                };
+                let direction = self.spherical_direction(s, t);
+
+                // each eye sits on a circle, offset sideways from the horizontal view direction
+                let longitude = (s - 0.5) * 2.0 * PI;
+                let right = (self.u * longitude.cos()) + (self.w * longitude.sin());
+                let origin = self.camera_center + right * (eye * interocular_distance / 2.0);
+                Some((origin, direction))
+            }
+            Projection::Fisheye { mapping, fov } => {
+                let x = 2.0 * s - 1.0;
+                let y = 1.0 - 2.0 * t;
+                let r = (x * x + y * y).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+
+                let half_fov = degrees_to_radians(fov.min(360.0)) / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).asin(),
+                };
+                let phi = y.atan2(x);
+
+                let direction = (((self.u * phi.cos()) + (self.v * phi.sin())) * theta.sin())
+                    + (forward * theta.cos());
+                Some((self.camera_center, direction))
+            }
+            Projection::CubeMap => {
+                let face = (s as usize).min(5);
+                let sc = 2.0 * (s - face as f64) - 1.0;
+                let tc = 2.0 * t - 1.0;
+
+                let (x, y, z) = match face {
+                    0 => (1.0, -tc, -sc),
+                    1 => (-1.0, -tc, sc),
+                    2 => (sc, 1.0, tc),
+                    3 => (sc, -1.0, -tc),
+                    4 => (sc, -tc, 1.0),
+                    _ => (-sc, -tc, -1.0),
+                };
+                let direction = (self.u * x) + (self.v * y) + (self.w * z);
+                Some((self.camera_center, direction))
+            }
+            Projection::Perspective | Projection::Orthographic { .. } => None,
+        }
+    }
+
+    // s and t both in [0, 1], longitude zero looks at lookat
+    fn spherical_direction(&self, s: f64, t: f64) -> Vec3 {
+        let longitude = (s - 0.5) * 2.0 * PI;
+        let latitude = (0.5 - t) * PI;
+        let forward = self.w * -1.0;
+
+        let horizontal = (self.u * longitude.sin()) + (forward * longitude.cos());
+        (horizontal * latitude.cos()) + (self.v * latitude.sin())
     }
 
     fn sample_square() -> Vec3 {
@@ -184,34 +682,654 @@ impl Camera {
         }
     }
 
-    fn defocus_disk_sample(&self) -> Point {
-        let p = random_in_unit_disk();
-        self.camera_center + (self.defocus_disk_u * p.x) + (self.defocus_disk_v * p.y)
+    // offset from the center of the lens, None when the barrel blocks it
+    fn defocus_disk_sample(&self, x: f64, y: f64) -> Option<Vec3> {
+        let p = self.aperture.sample();
+
+        if self.cat_eye > 0.0 {
+            // the barrel's opening is the aperture disk slid towards the middle of
+            // the frame, in proportion to how far the pixel is from it
+            let half_diagonal = (self.image_width as f64).hypot(self.image_height as f64) / 2.0;
+            let from_center = Vec3::new(
+                (x - self.image_width as f64 / 2.0) / half_diagonal,
+                (self.image_height as f64 / 2.0 - y) / half_diagonal,
+                0.0,
+            );
+            if (p + from_center * (2.0 * self.cat_eye)).length_squared() > 1.0 {
+                return None;
+            }
+        }
+
+        Some((self.defocus_disk_u * p.x) + (self.defocus_disk_v * p.y))
     }
 
     pub fn render(&mut self, world: HittableList) {
-        self.initialize();
+        let film = self.render_film(&world);
+        self.write_output(&film);
+    }
 
-        println!("P3\n{} {}\n255\n", self.image_width, self.image_height);
+    // carries on a render from its checkpoint up to samples_per_pixel, or the adaptive
+    // max_samples, which can be raised past what a finished render was taken to for
+    // a cleaner image unless the sampler is laid out for the original count
+    pub fn resume<P: AsRef<Path>>(&mut self, world: HittableList, checkpoint: P) -> io::Result<()> {
+        let film = self.resume_film(&world, checkpoint)?;
+        self.write_output(&film);
+        Ok(())
+    }
 
-        for y in 0..self.image_height {
-            eprint!("\rScanlines remaining: {}   ", (self.image_height - y));
-            for x in 0..self.image_width {
-                let mut pixel_color = Color {
-                    r: 0.0,
-                    g: 0.0,
-                    b: 0.0,
-                };
-                for _sample in 0..self.samples_per_pixel {
-                    let ray = self.get_ray_for_pixel(x as f64, y as f64);
-                    pixel_color += ray.color(&world, self.max_depth);
-                }
+    pub fn render_image(&mut self, world: &HittableList) -> Image {
+        let film = self.render_film(world);
+        film.to_image(self.exposure_scale)
+    }
+
+    pub fn render_film(&mut self, world: &HittableList) -> Film {
+        self.initialize(world);
+
+        let film = Film::new(0, 0, self.image_width as usize, self.image_height as usize);
+        self.render_passes(world, film)
+    }
 
-                pixel_color *= self.pixel_samples_scale;
-                pixel_color.write();
+    pub fn resume_film<P: AsRef<Path>>(
+        &mut self,
+        world: &HittableList,
+        checkpoint: P,
+    ) -> io::Result<Film> {
+        let (film, settings) = Film::load_checkpoint(checkpoint)?;
+        self.initialize(world);
+
+        if (film.x0, film.y0, film.width, film.height)
+            != (0, 0, self.image_width as usize, self.image_height as usize)
+        {
+            return Err(invalid_data("checkpoint is for a different image size"));
+        }
+        if settings.filter != self.filter {
+            return Err(invalid_data(
+                "checkpoint was rendered with a different filter",
+            ));
+        }
+        if settings.exposure_scale != self.exposure_scale {
+            return Err(invalid_data(
+                "checkpoint was rendered with a different exposure",
+            ));
+        }
+        if settings.sampler != self.sampler.name() {
+            return Err(invalid_data(
+                "checkpoint was rendered with a different sampler",
+            ));
+        }
+        // max_samples is how far to take the render, a resume can raise it like
+        // samples_per_pixel
+        let criteria = |adaptive: Option<Adaptive>| {
+            adaptive.map(|adaptive| (adaptive.noise_threshold, adaptive.min_samples))
+        };
+        if criteria(settings.adaptive) != criteria(self.adaptive) {
+            return Err(invalid_data(
+                "checkpoint was rendered with different adaptive sampling",
+            ));
+        }
+        if self.sampler.uses_count() && settings.max_samples != self.max_samples() {
+            return Err(invalid_data(
+                "the checkpoint's sampler is laid out for its sample count, which can't change",
+            ));
+        }
+        // the same seed picks up the sample sequence where each pixel left off
+        self.seed = settings.seed;
+
+        Ok(self.render_passes(world, film))
+    }
+
+    fn write_output(&self, film: &Film) {
+        if let Some(path) = &self.heatmap {
+            if let Err(error) = film.sample_heatmap().save_ppm_linear(path) {
+                eprintln!("couldn't write heatmap to {path}: {error}");
+            }
+        }
+        film.to_image(self.exposure_scale).write_ppm();
+    }
+
+    // renders pass_samples more samples per pixel at a time, checkpointing between
+    // passes, until every pixel has max_samples or has converged
+    fn render_passes(&self, world: &HittableList, mut film: Film) -> Film {
+        let max_samples = self.max_samples();
+        let pass_samples = self.pass_samples.max(1);
+
+        let fewest = (0..film.height as i64)
+            .flat_map(|y| (0..film.width as i64).map(move |x| (x, y)))
+            .map(|(x, y)| film.stats(x, y).count)
+            .min()
+            .unwrap_or(max_samples);
+        if fewest >= max_samples {
+            return film;
+        }
+        let mut pass_end = (fewest / pass_samples + 1) * pass_samples;
+        let mut last_checkpoint = Instant::now();
+
+        loop {
+            pass_end = pass_end.min(max_samples);
+            eprintln!("\rPass up to {pass_end} of {max_samples} samples per pixel");
+
+            let pass = self.render_pass(world, &film, pass_end);
+            film.merge(&pass);
+
+            let finished = pass_end >= max_samples;
+            if let Some(path) = &self.checkpoint {
+                if finished || last_checkpoint.elapsed().as_secs_f64() >= self.checkpoint_interval {
+                    match film.save_checkpoint(path, &self.checkpoint_settings()) {
+                        Ok(()) => eprintln!("\rCheckpoint written to {path}           "),
+                        Err(error) => eprintln!("couldn't write checkpoint to {path}: {error}"),
+                    }
+                    last_checkpoint = Instant::now();
+                }
+            }
+            if finished {
+                break;
             }
+            pass_end += pass_samples;
         }
 
         eprint!("\rDone.                                 \n");
+        film
+    }
+
+    // takes every pixel up to pass_end samples, on top of what film already has
+    fn render_pass(&self, world: &HittableList, film: &Film, pass_end: u32) -> Film {
+        let (width, height) = (self.image_width, self.image_height);
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+        let tile_count = (tiles_x * tiles_y) as usize;
+
+        let threads = match self.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+        .min(tile_count);
+
+        // tiles splat into their own padded film and get summed into this one, so
+        // samples landing next to a tile edge still reach the neighbouring tile's pixels
+        let pass = Mutex::new(Film::new(0, 0, width as usize, height as usize));
+        let next_tile = AtomicUsize::new(0);
+        let tiles_done = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    sampler::install(Some(self.sampler.clone_box()));
+
+                    loop {
+                        let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if tile >= tile_count {
+                            break;
+                        }
+
+                        let x0 = (tile as u32 % tiles_x) * TILE_SIZE;
+                        let y0 = (tile as u32 / tiles_x) * TILE_SIZE;
+                        let tile_film = self.render_tile(
+                            world,
+                            film,
+                            pass_end,
+                            (x0, y0),
+                            ((x0 + TILE_SIZE).min(width), (y0 + TILE_SIZE).min(height)),
+                        );
+                        pass.lock().unwrap().merge(&tile_film);
+
+                        let done = tiles_done.fetch_add(1, Ordering::Relaxed) + 1;
+                        eprint!("\rTiles remaining: {}   ", tile_count - done);
+                    }
+
+                    sampler::install(None);
+                });
+            }
+        });
+
+        pass.into_inner().unwrap()
+    }
+
+    fn render_tile(
+        &self,
+        world: &HittableList,
+        film: &Film,
+        pass_end: u32,
+        (x0, y0): (u32, u32),
+        (x1, y1): (u32, u32),
+    ) -> Film {
+        let padding = self.filter.radius().ceil() as i64;
+        let mut tile = Film::new(
+            x0 as i64 - padding,
+            y0 as i64 - padding,
+            (x1 - x0) as usize + 2 * padding as usize,
+            (y1 - y0) as usize + 2 * padding as usize,
+        );
+
+        // stratified samples are laid out for this many, which is why a resume can't
+        // change it for them
+        let max_samples = self.max_samples();
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // every sample so far, and just the ones this pass adds
+                let mut total = film.stats(x as i64, y as i64);
+                let mut stats = Welford::default();
+
+                while total.count < pass_end
+                    && !self
+                        .adaptive
+                        .is_some_and(|adaptive| adaptive.converged(&total))
+                {
+                    sampler::start_sample(SampleIndex {
+                        x,
+                        y,
+                        index: total.count,
+                        count: max_samples,
+                        seed: self.seed,
+                    });
+
+                    let offset = Self::sample_square();
+                    let film_x = x as f64 + 0.5 + offset.x;
+                    let film_y = y as f64 + 0.5 + offset.y;
+
+                    // samples the projection doesn't cover still count, as black
+                    let color = match self.get_ray(film_x, film_y) {
+                        Some(ray) => ray.color(world, self.background.as_ref(), self.max_depth),
+                        None => Color::new(0.0, 0.0, 0.0),
+                    };
+                    tile.add_sample(film_x, film_y, color, &self.filter);
+
+                    // judged on the displayed brightness so the threshold means the same with any exposure
+                    let luminance = color.luminance() * self.exposure_scale;
+                    total.add(luminance);
+                    stats.add(luminance);
+                }
+
+                tile.add_stats(x as i64, y as i64, &stats);
+            }
+        }
+
+        tile
+    }
+
+    fn checkpoint_settings(&self) -> CheckpointSettings {
+        CheckpointSettings {
+            seed: self.seed,
+            max_samples: self.max_samples(),
+            filter: self.filter,
+            exposure_scale: self.exposure_scale,
+            sampler: self.sampler.name().to_string(),
+            adaptive: self.adaptive,
+        }
+    }
+
+    fn max_samples(&self) -> u32 {
+        self.adaptive
+            .map_or(self.samples_per_pixel, |adaptive| adaptive.max_samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::{
+        background::EnvironmentMap,
+        materials::Lambertian,
+        sampler::{Halton, Sobol, Stratified},
+        seed_random,
+        shapes::Sphere,
+    };
+
+    fn world() -> HittableList {
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::new(
+            Point::new(0.0, 0.0, -2.0),
+            1.0,
+            Box::new(Lambertian::new(Color::new(0.5, 0.7, 0.3))),
+        )));
+        world
+    }
+
+    fn camera(sampler: Box<dyn Sampler>, samples_per_pixel: u32) -> Camera {
+        Camera {
+            image_width: 10,
+            aspect_ratio: 1.0,
+            samples_per_pixel,
+            pass_samples: 4,
+            max_depth: 4,
+            threads: 1,
+            lookfrom: Point::new(0.0, 0.0, 0.0),
+            lookat: Point::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            sampler,
+            seed: 5,
+            ..Camera::default()
+        }
+    }
+
+    fn checkpoint_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("camera-{}-{name}.ckpt", std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn more_matches_a_single_render() {
+        let samplers: [(&str, Box<dyn Sampler>); 3] = [
+            ("independent", Box::new(Independent::default())),
+            ("sobol", Box::new(Sobol::default())),
+            ("halton", Box::new(Halton::default())),
+        ];
+
+        for (name, sampler) in samplers {
+            let single = camera(sampler.clone_box(), 16).render_image(&world());
+            assert!(single.pixels.iter().all(|p| p.luminance() > 0.0), "{name}");
+
+            let path = checkpoint_path(name);
+            let mut first = camera(sampler.clone_box(), 8);
+            first.checkpoint = Some(path.clone());
+            first.render_film(&world());
+
+            let mut more = camera(sampler, 16);
+            let resumed = more.resume_film(&world(), &path).unwrap().to_image(1.0);
+            std::fs::remove_file(&path).unwrap();
+
+            for (a, b) in single.pixels.iter().zip(&resumed.pixels) {
+                assert_eq!((a.r, a.g, a.b), (b.r, b.g, b.b), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn more_is_refused_for_stratified() {
+        let path = checkpoint_path("stratified");
+        let mut first = camera(Box::new(Stratified::default()), 8);
+        first.checkpoint = Some(path.clone());
+        first.render_film(&world());
+
+        // carrying on to the same count is fine, changing it isn't
+        assert!(camera(Box::new(Stratified::default()), 8)
+            .resume_film(&world(), &path)
+            .is_ok());
+        assert!(camera(Box::new(Stratified::default()), 16)
+            .resume_film(&world(), &path)
+            .is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn orthographic() -> Camera {
+        Camera {
+            image_width: 20,
+            aspect_ratio: 2.0,
+            lookfrom: Point::new(1.0, 2.0, 3.0),
+            lookat: Point::new(1.0, 2.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            projection: Projection::Orthographic { view_height: 2.0 },
+            ..Camera::default()
+        }
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let mut camera = orthographic();
+        camera.initialize(&HittableList::default());
+
+        for (x, y) in [(0.0, 0.0), (20.0, 10.0), (3.5, 7.25), (10.0, 5.0)] {
+            let ray = camera.get_ray(x, y).unwrap();
+            let direction = ray.direction.unit_vector();
+            assert!((direction - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+
+            // the image plane is view_height tall and twice as wide, through lookfrom
+            let expected = Point::new(1.0 - 2.0 + x / 5.0, 2.0 + 1.0 - y / 5.0, 3.0);
+            assert!((ray.origin - expected).length() < 1e-12, "{}", ray.origin);
+        }
+    }
+
+    #[test]
+    fn orthographic_defocus_meets_on_the_focus_plane() {
+        seed_random(11);
+        let mut camera = Camera {
+            defocus_angle: 10.0,
+            focus: Focus::Manual,
+            focus_dist: 2.0,
+            ..orthographic()
+        };
+        camera.initialize(&HittableList::default());
+
+        // every lens sample for a pixel crosses its chief ray on the plane of focus
+        let sharp = Point::new(1.0 - 2.0 + 3.5 / 5.0, 2.0 + 1.0 - 7.25 / 5.0, 1.0);
+        let mut spread = 0.0;
+        for _ in 0..100 {
+            let ray = camera.get_ray(3.5, 7.25).unwrap();
+            let t = (ray.origin.z - 1.0) / -ray.direction.z;
+            assert!((ray.at(t) - sharp).length() < 1e-9);
+            spread += (ray.origin - Point::new(sharp.x, sharp.y, 3.0)).length();
+        }
+        assert!(spread > 0.0);
+    }
+
+    fn panorama(projection: Projection, image_width: u32) -> Camera {
+        let mut camera = Camera {
+            image_width,
+            lookfrom: Point::new(0.0, 0.0, 0.0),
+            lookat: Point::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            projection,
+            ..Camera::default()
+        };
+        camera.initialize(&HittableList::default());
+        camera
+    }
+
+    #[test]
+    fn cube_map_faces_look_down_their_axes() {
+        let camera = panorama(Projection::CubeMap, 60);
+        let axes = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ];
+        for (face, axis) in axes.into_iter().enumerate() {
+            let ray = camera.get_ray(face as f64 * 10.0 + 5.0, 5.0).unwrap();
+            assert!(
+                (ray.direction.unit_vector() - axis).length() < 1e-12,
+                "{face}"
+            );
+        }
+    }
+
+    // every texel's direction has to land back on that texel under the opengl
+    // face selection rules
+    #[test]
+    fn cube_map_directions_round_trip() {
+        let camera = panorama(Projection::CubeMap, 48);
+        for y in 0..8 {
+            for x in 0..48 {
+                let d = camera
+                    .get_ray(x as f64 + 0.5, y as f64 + 0.5)
+                    .unwrap()
+                    .direction;
+                let (face, sc, tc, ma) = if d.x.abs() >= d.y.abs() && d.x.abs() >= d.z.abs() {
+                    if d.x > 0.0 {
+                        (0, -d.z, -d.y, d.x)
+                    } else {
+                        (1, d.z, -d.y, -d.x)
+                    }
+                } else if d.y.abs() >= d.z.abs() {
+                    if d.y > 0.0 {
+                        (2, d.x, d.z, d.y)
+                    } else {
+                        (3, d.x, -d.z, -d.y)
+                    }
+                } else if d.z > 0.0 {
+                    (4, d.x, -d.y, d.z)
+                } else {
+                    (5, -d.x, -d.y, -d.z)
+                };
+
+                let s = face as f64 + (sc / ma + 1.0) / 2.0;
+                let t = (tc / ma + 1.0) / 2.0;
+                assert_eq!(((s * 8.0) as usize, (t * 8.0) as usize), (x, y));
+            }
+        }
+    }
+
+    // an equirectangular render has to read back as an environment map, so bake a
+    // map whose texels hold their own coordinates and look each pixel up in it
+    #[test]
+    fn equirectangular_round_trips_through_an_environment_map() {
+        let camera = panorama(Projection::Equirectangular, 32);
+        let mut image = Image::new(32, 16);
+        for y in 0..16 {
+            for x in 0..32 {
+                image.set(x, y, Color::new(x as f64 + 1.0, y as f64 + 1.0, 1.0));
+            }
+        }
+        let map = EnvironmentMap::new(image);
+
+        for y in 0..16 {
+            for x in 0..32 {
+                let ray = camera.get_ray(x as f64 + 0.5, y as f64 + 0.5).unwrap();
+                let texel = map.color(ray.direction);
+                assert_eq!((texel.r, texel.g), (x as f64 + 1.0, y as f64 + 1.0));
+            }
+        }
+
+        // the middle of the image looks at lookat
+        let center = camera.get_ray(16.0, 8.0).unwrap().direction;
+        assert!((center.unit_vector() - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn fisheye_reaches_half_its_field_of_view_at_the_rim() {
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera = panorama(
+                Projection::Fisheye {
+                    mapping,
+                    fov: 180.0,
+                },
+                40,
+            );
+
+            let center = camera.get_ray(20.0, 20.0).unwrap().direction;
+            assert!((center.unit_vector() - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+
+            let rim = camera.get_ray(40.0, 20.0).unwrap().direction;
+            assert!((rim.unit_vector() - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+            assert!(camera.get_ray(0.5, 0.5).is_none());
+        }
+    }
+
+    #[test]
+    fn undistort_inverts_distort() {
+        let lenses = [
+            Distortion::radial(-0.2, 0.05, 0.0),
+            Distortion::radial(0.1, 0.02, 0.005),
+            Distortion::new(-0.1, 0.01, 0.0, 0.01, -0.02),
+        ];
+        for distortion in lenses {
+            for i in 0..=20 {
+                for j in 0..=20 {
+                    let (x, y) = (i as f64 / 10.0 - 1.0, j as f64 / 10.0 - 1.0);
+
+                    let (dx, dy) = distortion.distort(x, y);
+                    let (ux, uy) = distortion.undistort(dx, dy);
+                    assert!(
+                        (ux - x).hypot(uy - y) < 1e-9,
+                        "({x}, {y}) came back as ({ux}, {uy})"
+                    );
+
+                    let (ux, uy) = distortion.undistort(x, y);
+                    let (dx, dy) = distortion.distort(ux, uy);
+                    assert!(
+                        (dx - x).hypot(dy - y) < 1e-9,
+                        "({x}, {y}) came back as ({dx}, {dy})"
+                    );
+                }
+            }
+        }
+    }
+
+    fn focused(focus: Focus, projection: Projection) -> f64 {
+        let mut world = HittableList::default();
+        world.add_named(
+            "ball",
+            Box::new(Sphere::new(
+                Point::new(1.0, 0.0, -6.0),
+                1.0,
+                Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            )),
+        );
+
+        let mut camera = Camera {
+            image_width: 21,
+            aspect_ratio: 1.0,
+            vfov: 90.0,
+            lookfrom: Point::new(1.0, 0.0, 0.0),
+            lookat: Point::new(1.0, 0.0, -3.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            focus,
+            focus_dist: 1.5,
+            projection,
+            ..Camera::default()
+        };
+        camera.initialize(&world);
+        camera.focus_dist
+    }
+
+    #[test]
+    fn focus_resolves_to_the_scene() {
+        let perspective = Projection::Perspective;
+        let orthographic = Projection::Orthographic { view_height: 4.0 };
+
+        assert_eq!(focused(Focus::Manual, perspective), 1.5);
+        assert!((focused(Focus::LookAt, perspective) - 3.0).abs() < 1e-12);
+        assert!((focused(Focus::Object("ball".to_string()), perspective) - 6.0).abs() < 1e-12);
+
+        // the middle pixel looks straight at the near side of the ball
+        let probe = Focus::Probe { x: 10, y: 10 };
+        assert!((focused(probe, perspective) - 5.0).abs() < 1e-9);
+        let probe = Focus::Probe { x: 10, y: 10 };
+        assert!((focused(probe, orthographic) - 5.0).abs() < 1e-9);
+
+        // the top left corner sees past the ball, so focus_dist is left alone
+        assert_eq!(focused(Focus::Probe { x: 0, y: 0 }, perspective), 1.5);
+        assert_eq!(
+            focused(Focus::Object("nothing".to_string()), perspective),
+            1.5
+        );
+    }
+
+    // a probe off the axis focuses on the depth of what it hits, not the distance to it
+    #[test]
+    fn probe_focuses_on_depth() {
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::new(
+            Point::new(4.0, 0.0, -4.0),
+            1.0,
+            Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+
+        let mut camera = Camera {
+            image_width: 21,
+            aspect_ratio: 1.0,
+            vfov: 90.0,
+            lookfrom: Point::new(0.0, 0.0, 0.0),
+            lookat: Point::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            focus: Focus::Probe { x: 20, y: 10 },
+            ..Camera::default()
+        };
+        camera.initialize(&world);
+
+        // the probe through the middle of the last column runs along x = -k z, and
+        // meets the sphere where (k d - 4)^2 + (d - 4)^2 = 1 at depth d
+        let k: f64 = 2.0 * (20.5 / 21.0 - 0.5);
+        let (a, b, c) = (k * k + 1.0, -8.0 * (k + 1.0), 31.0);
+        let depth = (-b - (b * b - 4.0 * a * c).sqrt()) / (2.0 * a);
+        assert!(
+            (camera.focus_dist - depth).abs() < 1e-9,
+            "{}",
+            camera.focus_dist
+        );
     }
 }
@@ -1,11 +1,9 @@
-use std::ops::{AddAssign, Mul, MulAssign};
+use std::ops::{Add, AddAssign, Mul, MulAssign};
 
 use crate::raytracing::interval::Interval;
 
 use super::{random_float, random_float_range};
 
-const MULTIPLIER: f64 = 255.999;
-
 #[derive(Clone, Copy)]
 pub struct Color {
     pub r: f64,
@@ -27,23 +25,51 @@ impl Color {
     }
 
     pub fn write(&self) {
+        let [rbyte, gbyte, bbyte] = self.to_bytes();
+
+        println!("{} {} {}", rbyte, gbyte, bbyte);
+    }
+
+    // gamma corrected and clamped to 8 bits
+    pub fn to_bytes(&self) -> [u8; 3] {
+        Color::new(
+            Self::linear_to_gamma(self.r),
+            Self::linear_to_gamma(self.g),
+            Self::linear_to_gamma(self.b),
+        )
+        .to_linear_bytes()
+    }
+
+    // clamped to 8 bits as they are, for data that isn't radiance
+    pub fn to_linear_bytes(&self) -> [u8; 3] {
         const INTENSITY: Interval = Interval {
             min: 0.0,
             max: 0.999,
         };
 
-        let r = Self::linear_to_gamma(self.r);
-        let g = Self::linear_to_gamma(self.g);
-        let b = Self::linear_to_gamma(self.b);
-        // let rbyte: u32 = (MULTIPLIER * self.r) as u32;
-        // let gbyte: u32 = (MULTIPLIER * self.g) as u32;
-        // let bbyte: u32 = (MULTIPLIER * self.b) as u32;
+        [
+            (256.0 * INTENSITY.clamp(self.r)) as u8,
+            (256.0 * INTENSITY.clamp(self.g)) as u8,
+            (256.0 * INTENSITY.clamp(self.b)) as u8,
+        ]
+    }
 
-        let rbyte = (256.0 * INTENSITY.clamp(r)) as u32;
-        let gbyte = (256.0 * INTENSITY.clamp(g)) as u32;
-        let bbyte = (256.0 * INTENSITY.clamp(b)) as u32;
+    // cie xyz to linear srgb
+    pub fn from_xyz(x: f64, y: f64, z: f64) -> Self {
+        Self {
+            r: 3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+            g: -0.969266 * x + 1.8760108 * y + 0.041556 * z,
+            b: 0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+        }
+    }
 
-        println!("{} {} {}", rbyte, gbyte, bbyte);
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn near_zero(&self) -> bool {
+        const S: f64 = 1e-8_f64;
+        (self.r.abs() < S) && (self.g.abs() < S) && (self.b.abs() < S)
     }
 
     pub fn random() -> Self {
@@ -87,6 +113,18 @@ impl Mul<Color> for Color {
     }
 }
 
+impl Add for Color {
+    type Output = Self;
+
+    fn add(self, other: Color) -> Self {
+        Self {
+            r: self.r + other.r,
+            g: self.g + other.g,
+            b: self.b + other.b,
+        }
+    }
+}
+
 impl AddAssign for Color {
     fn add_assign(&mut self, rhs: Self) {
         *self = Self {
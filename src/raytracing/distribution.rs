@@ -0,0 +1,174 @@
+// piecewise-constant distributions, used to importance sample images
+
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Self {
+        assert!(!func.is_empty(), "distribution needs at least one value");
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].max(0.0) / n as f64;
+        }
+
+        let integral = cdf[n];
+        if integral > 0.0 {
+            for value in cdf.iter_mut() {
+                *value /= integral;
+            }
+        } else {
+            // nothing to importance sample, fall back to uniform
+            for (i, value) in cdf.iter_mut().enumerate() {
+                *value = i as f64 / n as f64;
+            }
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // returns the continuous sample in [0, 1), its pdf and the bucket it landed in
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.count();
+        let offset = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+
+        let value = (offset as f64 + du) / n as f64;
+        (value, self.pdf(offset), offset)
+    }
+
+    pub fn pdf(&self, offset: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[offset].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    // values are laid out row by row, with `width` values per row
+    pub fn new(values: &[f64], width: usize, height: usize) -> Self {
+        assert!(
+            width > 0 && height > 0,
+            "distribution needs a non-empty image, got {width}x{height}"
+        );
+        assert_eq!(values.len(), width * height, "distribution size mismatch");
+        let conditional: Vec<Distribution1D> = (0..height)
+            .map(|y| Distribution1D::new(values[y * width..(y + 1) * width].to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+
+        Self {
+            conditional,
+            marginal,
+        }
+    }
+
+    // returns (u, v) in [0, 1)^2 and the pdf with respect to that square
+    pub fn sample(&self, u0: f64, u1: f64) -> (f64, f64, f64) {
+        let (v, pdf_v, row) = self.marginal.sample(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample(u0);
+        (u, v, pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let width = self.conditional[0].count();
+        let height = self.marginal.count();
+        let x = ((u * width as f64) as usize).min(width - 1);
+        let y = ((v * height as f64) as usize).min(height - 1);
+
+        self.conditional[y].pdf(x) * self.marginal.pdf(y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [f64; 12] = [0.0, 1.0, 2.0, 3.0, 4.0, 0.5, 8.0, 0.0, 1.0, 1.0, 6.0, 2.5];
+
+    #[test]
+    fn sample_pdf_matches_pdf() {
+        let distribution = Distribution2D::new(&VALUES, 4, 3);
+        for i in 0..32 {
+            for j in 0..32 {
+                let (u0, u1) = ((i as f64 + 0.5) / 32.0, (j as f64 + 0.5) / 32.0);
+                let (u, v, pdf) = distribution.sample(u0, u1);
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                assert!((pdf - distribution.pdf(u, v)).abs() < 1e-12);
+                assert!(pdf > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let distribution = Distribution2D::new(&VALUES, 4, 3);
+        let total: f64 = (0..3)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .map(|(x, y)| distribution.pdf((x as f64 + 0.5) / 4.0, (y as f64 + 0.5) / 3.0) / 12.0)
+            .sum();
+        assert!((total - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn samples_land_in_proportion_to_values() {
+        let distribution = Distribution2D::new(&VALUES, 4, 3);
+        let mut counts = [0usize; 12];
+        let n = 600;
+        for i in 0..n {
+            for j in 0..n {
+                let (u0, u1) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let (u, v, _) = distribution.sample(u0, u1);
+                counts[(v * 3.0) as usize * 4 + (u * 4.0) as usize] += 1;
+            }
+        }
+
+        let sum: f64 = VALUES.iter().sum();
+        for (count, value) in counts.iter().zip(VALUES) {
+            let expected = value / sum;
+            assert!((*count as f64 / (n * n) as f64 - expected).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "non-empty")]
+    fn empty_image_is_rejected() {
+        Distribution2D::new(&[], 0, 0);
+    }
+
+    #[test]
+    fn all_zero_falls_back_to_uniform() {
+        let distribution = Distribution2D::new(&[0.0; 6], 3, 2);
+        let (u, v, pdf) = distribution.sample(0.4, 0.7);
+        assert!((u - 0.4).abs() < 1e-12 && (v - 0.7).abs() < 1e-12);
+        assert_eq!(pdf, 1.0);
+    }
+}
@@ -0,0 +1,586 @@
+use std::{fs, io, path::Path};
+
+use super::{
+    adaptive::{Adaptive, Welford},
+    color::Color,
+    hdr::invalid_data,
+    image::Image,
+    PI,
+};
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RTFILM02";
+// the sample count, then r, g, b, weight, mean and m2
+const CHECKPOINT_PIXEL_SIZE: usize = 4 + 6 * 8;
+
+// what the samples in a checkpoint were taken with besides the scene. resuming
+// with different settings would mix samples that don't belong together
+#[derive(Clone)]
+pub struct CheckpointSettings {
+    pub seed: u64,
+    // samples per pixel the render was being taken to
+    pub max_samples: u32,
+    pub filter: Filter,
+    pub exposure_scale: f64,
+    pub sampler: String,
+    pub adaptive: Option<Adaptive>,
+}
+
+// reconstruction filters, all separable. radius is in pixels and anything past
+// 0.5 spreads a sample over the neighbouring pixels too
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64, sigma: f64 },
+    // b = c = 1/3 is the pair Mitchell and Netravali recommend
+    Mitchell { radius: f64, b: f64, c: f64 },
+    // sinc windowed by a wider sinc, tau is the number of lobes
+    Lanczos { radius: f64, tau: f64 },
+    BlackmanHarris { radius: f64 },
+}
+
+impl Default for Filter {
+    // one sample box per pixel, same as a plain average
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. }
+            | Filter::BlackmanHarris { radius } => radius,
+        }
+    }
+
+    // a tag and up to three parameters, as checkpoints store it
+    fn to_params(self) -> (u64, [f64; 3]) {
+        match self {
+            Filter::Box { radius } => (0, [radius, 0.0, 0.0]),
+            Filter::Tent { radius } => (1, [radius, 0.0, 0.0]),
+            Filter::Gaussian { radius, sigma } => (2, [radius, sigma, 0.0]),
+            Filter::Mitchell { radius, b, c } => (3, [radius, b, c]),
+            Filter::Lanczos { radius, tau } => (4, [radius, tau, 0.0]),
+            Filter::BlackmanHarris { radius } => (5, [radius, 0.0, 0.0]),
+        }
+    }
+
+    fn from_params(tag: u64, [radius, a, b]: [f64; 3]) -> Option<Self> {
+        Some(match tag {
+            0 => Filter::Box { radius },
+            1 => Filter::Tent { radius },
+            2 => Filter::Gaussian { radius, sigma: a },
+            3 => Filter::Mitchell { radius, b: a, c: b },
+            4 => Filter::Lanczos { radius, tau: a },
+            5 => Filter::BlackmanHarris { radius },
+            _ => return None,
+        })
+    }
+
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => radius - x,
+            Filter::Gaussian { radius, sigma } => {
+                // shifted down so it reaches zero at the radius instead of cutting off
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                let x = 2.0 * x / radius;
+                if x > 1.0 {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau),
+            Filter::BlackmanHarris { radius } => {
+                let t = (x + radius) / (2.0 * radius);
+                0.35875 - 0.48829 * (2.0 * PI * t).cos() + 0.14128 * (4.0 * PI * t).cos()
+                    - 0.01168 * (6.0 * PI * t).cos()
+            }
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// weighted sums of splatted samples over a window of the image. tiles render into
+// their own film, padded by the filter radius, and get merged into the full one
+pub struct Film {
+    pub x0: i64,
+    pub y0: i64,
+    pub width: usize,
+    pub height: usize,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+    // of the samples started in each pixel, before filtering
+    stats: Vec<Welford>,
+}
+
+impl Film {
+    pub fn new(x0: i64, y0: i64, width: usize, height: usize) -> Self {
+        Self {
+            x0,
+            y0,
+            width,
+            height,
+            sums: vec![Color::new(0.0, 0.0, 0.0); width * height],
+            weights: vec![0.0; width * height],
+            stats: vec![Welford::default(); width * height],
+        }
+    }
+
+    // x and y are continuous image coordinates, pixel (i, j) covers [i, i + 1) x [j, j + 1)
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color, filter: &Filter) {
+        let radius = filter.radius();
+        let px0 = ((x - 0.5 - radius).ceil() as i64).max(self.x0);
+        let px1 = ((x - 0.5 + radius).floor() as i64).min(self.x0 + self.width as i64 - 1);
+        let py0 = ((y - 0.5 - radius).ceil() as i64).max(self.y0);
+        let py1 = ((y - 0.5 + radius).floor() as i64).min(self.y0 + self.height as i64 - 1);
+
+        for py in py0..=py1 {
+            for px in px0..=px1 {
+                let weight = filter.evaluate(px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+                if weight == 0.0 {
+                    continue;
+                }
+
+                let index = self.index(px, py);
+                self.sums[index] += color * weight;
+                self.weights[index] += weight;
+            }
+        }
+    }
+
+    // adds another film's sums into the overlapping part of this one
+    pub fn merge(&mut self, other: &Film) {
+        for y in 0..other.height as i64 {
+            for x in 0..other.width as i64 {
+                let (px, py) = (other.x0 + x, other.y0 + y);
+                if !self.contains(px, py) {
+                    continue;
+                }
+
+                let from = other.index(px, py);
+                let to = self.index(px, py);
+                self.sums[to] += other.sums[from];
+                self.weights[to] += other.weights[from];
+                self.stats[to].merge(&other.stats[from]);
+            }
+        }
+    }
+
+    pub fn add_stats(&mut self, x: i64, y: i64, stats: &Welford) {
+        let index = self.index(x, y);
+        self.stats[index].merge(stats);
+    }
+
+    pub fn stats(&self, x: i64, y: i64) -> Welford {
+        self.stats[self.index(x, y)]
+    }
+
+    pub fn to_image(&self, scale: f64) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            // negative lobes can leave a pixel with no weight at all
+            if self.weights[i] > 0.0 {
+                *pixel = self.sums[i] * (scale / self.weights[i]);
+            }
+        }
+        image
+    }
+
+    // samples taken per pixel, from black through red and yellow to white at the most.
+    // the ramp is in display values already so it wants saving without gamma
+    pub fn sample_heatmap(&self) -> Image {
+        let most = self.stats.iter().map(|s| s.count).max().unwrap_or(0).max(1);
+
+        let mut image = Image::new(self.width, self.height);
+        for (pixel, stats) in image.pixels.iter_mut().zip(&self.stats) {
+            let t = 3.0 * stats.count as f64 / most as f64;
+            *pixel = Color::new(
+                t.clamp(0.0, 1.0),
+                (t - 1.0).clamp(0.0, 1.0),
+                (t - 2.0).clamp(0.0, 1.0),
+            );
+        }
+        image
+    }
+
+    // everything needed to carry on sampling: the sums, the per pixel sample counts
+    // and the settings, whose seed with those counts is all the sampler state there is
+    pub fn save_checkpoint<P: AsRef<Path>>(
+        &self,
+        path: P,
+        settings: &CheckpointSettings,
+    ) -> io::Result<()> {
+        let (filter, filter_params) = settings.filter.to_params();
+        let (adaptive, noise_threshold, min_samples, max_samples) =
+            settings.adaptive.map_or((0, 0.0, 0, 0), |adaptive| {
+                (
+                    1,
+                    adaptive.noise_threshold,
+                    adaptive.min_samples,
+                    adaptive.max_samples,
+                )
+            });
+
+        let mut bytes = CHECKPOINT_MAGIC.to_vec();
+        for value in [
+            settings.seed,
+            settings.max_samples as u64,
+            filter,
+            adaptive,
+            min_samples as u64,
+            max_samples as u64,
+            settings.sampler.len() as u64,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(settings.sampler.as_bytes());
+        for value in filter_params
+            .into_iter()
+            .chain([settings.exposure_scale, noise_threshold])
+        {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [self.x0, self.y0, self.width as i64, self.height as i64] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        for i in 0..self.sums.len() {
+            let stats = &self.stats[i];
+            bytes.extend_from_slice(&stats.count.to_le_bytes());
+            let sum = self.sums[i];
+            for value in [sum.r, sum.g, sum.b, self.weights[i], stats.mean, stats.m2] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        // written beside it and moved over, so dying halfway keeps the last good one
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        fs::write(&partial, bytes)?;
+        fs::rename(partial, path)
+    }
+
+    // the film and the settings it was rendered with
+    pub fn load_checkpoint<P: AsRef<Path>>(path: P) -> io::Result<(Self, CheckpointSettings)> {
+        let bytes = fs::read(path)?;
+        if !bytes.starts_with(CHECKPOINT_MAGIC) {
+            return Err(invalid_data("not a film checkpoint"));
+        }
+
+        let mut position = CHECKPOINT_MAGIC.len();
+        let mut next = |size: usize| -> io::Result<&[u8]> {
+            let chunk = position
+                .checked_add(size)
+                .and_then(|end| bytes.get(position..end))
+                .ok_or_else(|| invalid_data("truncated film checkpoint"))?;
+            position += size;
+            Ok(chunk)
+        };
+
+        let mut words = [0u64; 7];
+        for value in words.iter_mut() {
+            *value = u64::from_le_bytes(next(8)?.try_into().unwrap());
+        }
+        let [seed, samples, filter, adaptive, min_samples, max_samples, sampler_length] = words;
+        let sampler = usize::try_from(sampler_length)
+            .map_err(|_| invalid_data("truncated film checkpoint"))
+            .and_then(&mut next)?;
+        let sampler = String::from_utf8(sampler.to_vec())
+            .map_err(|_| invalid_data("bad sampler name in film checkpoint"))?;
+
+        let mut values = [0.0; 5];
+        for value in values.iter_mut() {
+            *value = f64::from_le_bytes(next(8)?.try_into().unwrap());
+        }
+        let [radius, a, b, exposure_scale, noise_threshold] = values;
+        let filter = Filter::from_params(filter, [radius, a, b])
+            .ok_or_else(|| invalid_data("unknown filter in film checkpoint"))?;
+        let adaptive = match adaptive {
+            0 => None,
+            1 => Some(Adaptive {
+                noise_threshold,
+                min_samples: min_samples as u32,
+                max_samples: max_samples as u32,
+            }),
+            _ => return Err(invalid_data("bad adaptive settings in film checkpoint")),
+        };
+
+        let mut header = [0i64; 4];
+        for value in header.iter_mut() {
+            *value = i64::from_le_bytes(next(8)?.try_into().unwrap());
+        }
+        let [x0, y0, width, height] = header;
+
+        // checked against the file before anything the size of the image is allocated
+        let raster = &bytes[position..];
+        let size = usize::try_from(width)
+            .ok()
+            .zip(usize::try_from(height).ok())
+            .and_then(|(width, height)| width.checked_mul(height))
+            .and_then(|pixels| pixels.checked_mul(CHECKPOINT_PIXEL_SIZE));
+        if size != Some(raster.len()) {
+            return Err(invalid_data(
+                "film checkpoint size doesn't match its header",
+            ));
+        }
+
+        let mut film = Self::new(x0, y0, width as usize, height as usize);
+        for (i, pixel) in raster.chunks_exact(CHECKPOINT_PIXEL_SIZE).enumerate() {
+            let count = u32::from_le_bytes(pixel[..4].try_into().unwrap());
+            let mut values = [0.0; 6];
+            for (value, bytes) in values.iter_mut().zip(pixel[4..].chunks_exact(8)) {
+                *value = f64::from_le_bytes(bytes.try_into().unwrap());
+            }
+            let [r, g, b, weight, mean, m2] = values;
+
+            film.sums[i] = Color::new(r, g, b);
+            film.weights[i] = weight;
+            film.stats[i] = Welford { count, mean, m2 };
+        }
+
+        let settings = CheckpointSettings {
+            seed,
+            max_samples: samples as u32,
+            filter,
+            exposure_scale,
+            sampler,
+            adaptive,
+        };
+        Ok((film, settings))
+    }
+
+    fn contains(&self, x: i64, y: i64) -> bool {
+        x >= self.x0
+            && y >= self.y0
+            && x < self.x0 + self.width as i64
+            && y < self.y0 + self.height as i64
+    }
+
+    fn index(&self, x: i64, y: i64) -> usize {
+        (y - self.y0) as usize * self.width + (x - self.x0) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 6] = [
+        Filter::Box { radius: 0.5 },
+        Filter::Tent { radius: 1.0 },
+        Filter::Gaussian {
+            radius: 1.5,
+            sigma: 0.5,
+        },
+        Filter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        },
+        Filter::Lanczos {
+            radius: 3.0,
+            tau: 3.0,
+        },
+        Filter::BlackmanHarris { radius: 1.5 },
+    ];
+
+    // a grid of samples, 8 by 8 to a pixel
+    fn splat(film: &mut Film, filter: &Filter, color: impl Fn(f64, f64) -> Color) {
+        for j in 0..film.height * 8 {
+            for i in 0..film.width * 8 {
+                let (x, y) = ((i as f64 + 0.5) / 8.0, (j as f64 + 0.5) / 8.0);
+                film.add_sample(x, y, color(x, y), filter);
+            }
+        }
+    }
+
+    #[test]
+    fn filters_are_zero_past_their_radius() {
+        for filter in FILTERS {
+            let radius = filter.radius();
+            assert_eq!(filter.evaluate(radius + 1e-9, 0.0), 0.0);
+            assert_eq!(filter.evaluate(0.0, -radius - 1e-9), 0.0);
+            assert!(filter.evaluate(0.0, 0.0) > 0.0);
+        }
+    }
+
+    #[test]
+    fn constant_image_stays_constant() {
+        let color = Color::new(0.25, 0.5, 1.0);
+        for filter in FILTERS {
+            let mut film = Film::new(0, 0, 6, 5);
+            splat(&mut film, &filter, |_, _| color);
+
+            for pixel in film.to_image(2.0).pixels {
+                assert!((pixel.r - 0.5).abs() < 1e-9);
+                assert!((pixel.g - 1.0).abs() < 1e-9);
+                assert!((pixel.b - 2.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_merge_into_the_whole_film() {
+        let color = |x: f64, y: f64| Color::new(x, y, x * y);
+        let filter = Filter::Gaussian {
+            radius: 1.5,
+            sigma: 0.5,
+        };
+        let mut whole = Film::new(0, 0, 6, 4);
+        splat(&mut whole, &filter, color);
+
+        // each tile only takes its own samples but is padded to the filter radius
+        let mut merged = Film::new(0, 0, 6, 4);
+        for (x0, x1) in [(0, 3), (3, 6)] {
+            let mut tile = Film::new(x0 - 2, -2, (x1 - x0 + 4) as usize, 8);
+            for j in 0..4 * 8 {
+                for i in x0 * 8..x1 * 8 {
+                    let (x, y) = ((i as f64 + 0.5) / 8.0, (j as f64 + 0.5) / 8.0);
+                    tile.add_sample(x, y, color(x, y), &filter);
+                }
+            }
+            merged.merge(&tile);
+        }
+
+        for (a, b) in whole
+            .to_image(1.0)
+            .pixels
+            .iter()
+            .zip(merged.to_image(1.0).pixels)
+        {
+            assert!(
+                (a.r - b.r).abs() < 1e-9 && (a.g - b.g).abs() < 1e-9 && (a.b - b.b).abs() < 1e-9
+            );
+        }
+    }
+
+    fn settings() -> CheckpointSettings {
+        CheckpointSettings {
+            seed: 42,
+            max_samples: 96,
+            filter: Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            exposure_scale: 0.75,
+            sampler: "sobol".to_string(),
+            adaptive: Some(Adaptive::new(0.02, 8, 64)),
+        }
+    }
+
+    fn checkpoint_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("film-{}-{name}.ckpt", std::process::id()))
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let mut film = Film::new(-1, 2, 5, 3);
+        let filter = Filter::Tent { radius: 1.0 };
+        for i in 0..40 {
+            let (x, y) = (-1.0 + i as f64 * 0.13, 2.0 + i as f64 * 0.07);
+            film.add_sample(x, y, Color::new(x, y, 0.1 * i as f64), &filter);
+
+            let mut stats = Welford::default();
+            stats.add(i as f64);
+            stats.add(0.5 * i as f64);
+            film.add_stats(-1 + i % 5, 2 + i % 3, &stats);
+        }
+
+        let path = checkpoint_path("round-trip");
+        film.save_checkpoint(&path, &settings()).unwrap();
+        let (loaded, loaded_settings) = Film::load_checkpoint(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            (loaded.x0, loaded.y0, loaded.width, loaded.height),
+            (-1, 2, 5, 3)
+        );
+        for (a, b) in film
+            .to_image(1.0)
+            .pixels
+            .iter()
+            .zip(loaded.to_image(1.0).pixels)
+        {
+            assert_eq!((a.r, a.g, a.b), (b.r, b.g, b.b));
+        }
+        assert_eq!(film.weights, loaded.weights);
+        for (a, b) in film.stats.iter().zip(&loaded.stats) {
+            assert_eq!((a.count, a.mean, a.m2), (b.count, b.mean, b.m2));
+        }
+
+        let expected = settings();
+        assert_eq!(loaded_settings.seed, expected.seed);
+        assert_eq!(loaded_settings.max_samples, expected.max_samples);
+        assert!(loaded_settings.filter == expected.filter);
+        assert_eq!(loaded_settings.exposure_scale, expected.exposure_scale);
+        assert_eq!(loaded_settings.sampler, expected.sampler);
+        let adaptive = loaded_settings.adaptive.unwrap();
+        assert_eq!(
+            (
+                adaptive.noise_threshold,
+                adaptive.min_samples,
+                adaptive.max_samples
+            ),
+            (0.02, 8, 64)
+        );
+    }
+
+    #[test]
+    fn checkpoint_with_the_wrong_size_is_rejected() {
+        let path = checkpoint_path("wrong-size");
+        Film::new(0, 0, 4, 4)
+            .save_checkpoint(&path, &settings())
+            .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(Film::load_checkpoint(&path).is_err());
+
+        // a header claiming a huge image fails before anything is allocated for it
+        let mut huge = bytes.clone();
+        let width = bytes.len() - 4 * 4 * CHECKPOINT_PIXEL_SIZE - 16;
+        huge[width..width + 8].copy_from_slice(&(i64::MAX / 4).to_le_bytes());
+        std::fs::write(&path, &huge).unwrap();
+        assert!(Film::load_checkpoint(&path).is_err());
+
+        // and the untouched file still loads
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(Film::load_checkpoint(&path).unwrap().0.width, 4);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+use super::color::Color;
+
+// exact unpolarized fresnel reflectance at a dielectric interface, eta is
+// the index on the transmitted side over the index on the incident side
+pub fn dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+// exact fresnel reflectance for a conductor with complex index eta + ik, from pbrt
+pub fn conductor(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let cos2 = cos_theta_i * cos_theta_i;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+pub fn conductor_rgb(cos_theta_i: f64, eta: Color, k: Color) -> Color {
+    Color::new(
+        conductor(cos_theta_i, eta.r, k.r),
+        conductor(cos_theta_i, eta.g, k.g),
+        conductor(cos_theta_i, eta.b, k.b),
+    )
+}
+
+// a single interference layer on top of a surface, like a soap film or a lens coating
+#[derive(Clone, Copy)]
+pub struct ThinFilm {
+    // in nanometers
+    pub thickness: f64,
+    pub refraction_index: f64,
+}
+
+// wavelengths each rgb channel is evaluated at, in nanometers
+const CHANNEL_WAVELENGTHS: [f64; 3] = [650.0, 550.0, 450.0];
+
+impl ThinFilm {
+    pub fn new(thickness: f64, refraction_index: f64) -> Self {
+        Self {
+            thickness,
+            refraction_index,
+        }
+    }
+
+    // reflectance seen from a medium with index `incident` onto the film over a
+    // substrate with complex index eta + ik, k is zero for dielectrics
+    pub fn reflectance(
+        &self,
+        cos_theta_i: f64,
+        incident: f64,
+        eta: f64,
+        k: f64,
+        wavelength: f64,
+    ) -> f64 {
+        let n1 = Complex::new(incident, 0.0);
+        let n2 = Complex::new(self.refraction_index, 0.0);
+        let n3 = Complex::new(eta, k);
+
+        let cos1 = Complex::new(cos_theta_i.clamp(0.0, 1.0), 0.0);
+        let sin2 = Complex::new(1.0 - cos1.re * cos1.re, 0.0);
+
+        // snell's law in every layer, complex past the critical angle or in metals
+        let cos_in = |n: Complex| (Complex::new(1.0, 0.0) - sin2 * (n1 / n) * (n1 / n)).sqrt();
+        let cos2 = cos_in(n2);
+        let cos3 = cos_in(n3);
+
+        let phase = Complex::new(0.0, 4.0 * super::PI * self.thickness / wavelength) * n2 * cos2;
+        let shift = phase.exp();
+
+        let airy = |r12: Complex, r23: Complex| {
+            let r = (r12 + r23 * shift) / (Complex::new(1.0, 0.0) + r12 * r23 * shift);
+            r.norm_sqr()
+        };
+
+        let rs12 = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+        let rs23 = (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3);
+        let rp12 = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+        let rp23 = (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3);
+
+        (0.5 * (airy(rs12, rs23) + airy(rp12, rp23))).clamp(0.0, 1.0)
+    }
+
+    pub fn reflectance_rgb(&self, cos_theta_i: f64, incident: f64, eta: Color, k: Color) -> Color {
+        let [r, g, b] = CHANNEL_WAVELENGTHS;
+        Color::new(
+            self.reflectance(cos_theta_i, incident, eta.r, k.r, r),
+            self.reflectance(cos_theta_i, incident, eta.g, k.g, g),
+            self.reflectance(cos_theta_i, incident, eta.b, k.b, b),
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // principal root, which keeps the imaginary part non-negative so waves decay
+    fn sqrt(self) -> Self {
+        let r = self.norm_sqr().sqrt();
+        let re = ((r + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((r - self.re) / 2.0).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp(self) -> Self {
+        let magnitude = self.re.exp();
+        Self::new(magnitude * self.im.cos(), magnitude * self.im.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let denominator = other.norm_sqr();
+        Self::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn film_of_no_thickness_is_plain_fresnel() {
+        let film = ThinFilm::new(0.0, 1.33);
+        for (incident, eta) in [(1.0, 1.5), (1.5, 1.0), (1.0, 2.4)] {
+            for i in 0..=20 {
+                let cos_theta = i as f64 / 20.0;
+                let coated = film.reflectance(cos_theta, incident, eta, 0.0, 550.0);
+                let plain = dielectric(cos_theta, eta / incident);
+                assert!((coated - plain).abs() < 1e-9, "{coated} against {plain}");
+            }
+        }
+    }
+}
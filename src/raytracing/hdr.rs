@@ -0,0 +1,206 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use super::{color::Color, image::Image};
+
+// Radiance RGBE reader, format described here:
+// https://www.graphics.cornell.edu/~bjw/rgbe.html
+
+// larger than any real environment map, and small enough that a bad header can't
+// ask for more memory than the machine has
+const MAX_PIXELS: usize = 1 << 28;
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+    let file = File::open(path)?;
+    read(BufReader::new(file))
+}
+
+pub fn read<R: BufRead>(mut reader: R) -> io::Result<Image> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid_data("missing #? signature"));
+    }
+
+    // header lines run until the first empty line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("unexpected end of header"));
+        }
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some(format) = trimmed.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data("unsupported pixel format"));
+            }
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let (width, height) = parse_resolution(line.trim_end())?;
+    let count = width
+        .checked_mul(height)
+        .filter(|&count| count <= MAX_PIXELS)
+        .ok_or_else(|| invalid_data("image too large"))?;
+
+    let mut pixels = Vec::with_capacity(count);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _y in 0..height {
+        read_scanline(&mut reader, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|rgbe| rgbe_to_color(*rgbe)));
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+pub(super) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// only the standard "-Y height +X width" orientation is supported
+fn parse_resolution(line: &str) -> io::Result<(usize, usize)> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 4 || parts[0] != "-Y" || parts[2] != "+X" {
+        return Err(invalid_data("unsupported resolution line"));
+    }
+
+    let height = parts[1]
+        .parse::<usize>()
+        .map_err(|_| invalid_data("bad image height"))?;
+    let width = parts[3]
+        .parse::<usize>()
+        .map_err(|_| invalid_data("bad image width"))?;
+    if width == 0 || height == 0 {
+        return Err(invalid_data("empty image"));
+    }
+
+    Ok((width, height))
+}
+
+fn read_scanline<R: Read>(reader: &mut R, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+
+    let mut first = [0u8; 4];
+    reader.read_exact(&mut first)?;
+
+    let is_rle = (8..0x8000).contains(&width)
+        && first[0] == 2
+        && first[1] == 2
+        && ((first[2] as usize) << 8 | first[3] as usize) == width;
+
+    if !is_rle {
+        // flat scanline, the first pixel has already been read
+        scanline[0] = first;
+        for pixel in scanline.iter_mut().skip(1) {
+            reader.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+
+    // new-style rle: each of the four channels is encoded separately
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+
+            if count[0] > 128 {
+                let run = (count[0] - 128) as usize;
+                if x + run > width {
+                    return Err(invalid_data("rle run overflows scanline"));
+                }
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for pixel in &mut scanline[x..x + run] {
+                    pixel[channel] = value[0];
+                }
+                x += run;
+            } else {
+                let run = count[0] as usize;
+                if run == 0 || x + run > width {
+                    return Err(invalid_data("bad rle literal length"));
+                }
+                let mut values = vec![0u8; run];
+                reader.read_exact(&mut values)?;
+                for (pixel, value) in scanline[x..x + run].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                x += run;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let f = 2.0_f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * f,
+        (rgbe[1] as f64 + 0.5) * f,
+        (rgbe[2] as f64 + 0.5) * f,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(resolution: &str) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{resolution}\n").into_bytes()
+    }
+
+    #[test]
+    fn reads_flat_pixels() {
+        let mut bytes = header("-Y 1 +X 2");
+        bytes.extend_from_slice(&[128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = read(bytes.as_slice()).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 1));
+        let pixel = image.get(0, 0);
+        assert!((pixel.r - 128.5 / 128.0).abs() < 1e-12);
+        assert!((pixel.g - 64.5 / 128.0).abs() < 1e-12);
+        assert_eq!(image.get(1, 0).r, 0.0);
+    }
+
+    #[test]
+    fn rejects_bad_dimensions() {
+        for resolution in [
+            "-Y 0 +X 0",
+            "-Y 4 +X 0",
+            "-Y 0 +X 4",
+            "-Y 4000000000 +X 4000000000",
+            "-Y 18446744073709551615 +X 2",
+        ] {
+            let mut bytes = header(resolution);
+            bytes.extend_from_slice(&[0; 64]);
+            let result = read(bytes.as_slice());
+            assert!(
+                result.is_err_and(|error| error.kind() == io::ErrorKind::InvalidData),
+                "{resolution}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_pixels() {
+        let mut bytes = header("-Y 2 +X 2");
+        bytes.extend_from_slice(&[1, 2, 3, 128]);
+        assert!(read(bytes.as_slice()).is_err());
+    }
+}
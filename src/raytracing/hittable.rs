@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::raytracing::{
     ray::Ray,
     vec3::{dot, Point, Vec3},
@@ -6,23 +8,37 @@ use crate::raytracing::{
 use super::{
     color::Color,
     interval::Interval,
+    lights::Light,
     materials::{Lambertian, Material},
 };
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+
+    // somewhere in the middle of the object, for things like focusing on it
+    fn center(&self) -> Option<Point> {
+        None
+    }
 }
 
 pub struct HitRecord {
     pub point: Point,
+    // shading normal, facing against the ray. may be perturbed by the material
     pub normal: Vec3,
+    // true surface normal, facing the same side as normal
+    pub geometric_normal: Vec3,
     pub material: Box<dyn Material>,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    // surface derivatives along u and v, on the outward side of the surface
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
     pub front_face: bool,
 }
 
-impl HitRecord {
-    pub fn default() -> HitRecord {
+impl Default for HitRecord {
+    fn default() -> HitRecord {
         HitRecord {
             point: Point {
                 x: 0.0,
@@ -34,6 +50,7 @@ impl HitRecord {
                 y: 0.0,
                 z: 0.0,
             },
+            geometric_normal: Vec3::new(0.0, 0.0, 0.0),
             material: Box::new(Lambertian {
                 albedo: Color {
                     r: 0.0,
@@ -43,9 +60,15 @@ impl HitRecord {
             }),
             front_face: false,
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
         }
     }
+}
 
+impl HitRecord {
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
         self.front_face = dot(ray.direction, outward_normal) < 0.0;
         if self.front_face {
@@ -53,11 +76,25 @@ impl HitRecord {
         } else {
             self.normal = outward_normal * -1.0
         }
+        self.geometric_normal = self.normal;
+    }
+
+    // outward facing normal of the shading frame, whichever side was hit
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.normal
+        } else {
+            self.normal * -1.0
+        }
     }
 }
 
+#[derive(Default)]
 pub struct HittableList {
     pub objects: Vec<Box<dyn Hittable>>,
+    pub lights: Vec<Box<dyn Light>>,
+    // indices into objects
+    pub names: HashMap<String, usize>,
 }
 
 impl HittableList {
@@ -65,6 +102,21 @@ impl HittableList {
         self.objects.push(object)
     }
 
+    pub fn add_named(&mut self, name: &str, object: Box<dyn Hittable>) {
+        self.names.insert(name.to_string(), self.objects.len());
+        self.objects.push(object)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Hittable> {
+        self.names
+            .get(name)
+            .map(|&index| self.objects[index].as_ref())
+    }
+
+    pub fn add_light(&mut self, light: Box<dyn Light>) {
+        self.lights.push(light)
+    }
+
     pub fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
         let mut temp_rec: HitRecord = HitRecord::default();
         let mut hit_anything = false;
@@ -83,6 +135,11 @@ impl HittableList {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
                 rec.normal = temp_rec.normal;
+                rec.geometric_normal = temp_rec.geometric_normal;
+                rec.u = temp_rec.u;
+                rec.v = temp_rec.v;
+                rec.tangent = temp_rec.tangent;
+                rec.bitangent = temp_rec.bitangent;
                 rec.front_face = temp_rec.front_face;
                 rec.point = temp_rec.point;
                 rec.t = temp_rec.t;
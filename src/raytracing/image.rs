@@ -0,0 +1,227 @@
+use std::{fs, io, path::Path};
+
+use super::{color::Color, hdr, hdr::invalid_data};
+
+// linear rgb pixels, row by row from the top left
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    // .hdr files are read as linear radiance, anything else as a ppm. srgb decodes
+    // 8-bit colour data, normal and height maps should be loaded with it off
+    pub fn load<P: AsRef<Path>>(path: P, srgb: bool) -> io::Result<Self> {
+        let is_hdr = path
+            .as_ref()
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+
+        if is_hdr {
+            hdr::load(path)
+        } else {
+            Self::read_ppm(&fs::read(path)?, srgb)
+        }
+    }
+
+    // binary (P6) and plain (P3) netpbm images
+    pub fn read_ppm(bytes: &[u8], srgb: bool) -> io::Result<Self> {
+        let mut position = 0;
+        let magic = next_token(bytes, &mut position)?;
+        let binary = match magic.as_str() {
+            "P6" => true,
+            "P3" => false,
+            _ => return Err(invalid_data("not a P3 or P6 ppm")),
+        };
+
+        let mut header = [0usize; 3];
+        for value in header.iter_mut() {
+            *value = next_token(bytes, &mut position)?
+                .parse()
+                .map_err(|_| invalid_data("bad ppm header"))?;
+        }
+        let [width, height, max_value] = header;
+        if max_value == 0 || max_value > 65535 {
+            return Err(invalid_data("bad ppm max value"));
+        }
+
+        if width == 0 || height == 0 {
+            return Err(invalid_data("empty ppm"));
+        }
+
+        // every sample takes at least `size` bytes, or a digit in a plain ppm, so a header
+        // promising more than the file holds is caught before anything is allocated
+        let size = if max_value < 256 { 1 } else { 2 };
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .filter(|count| {
+                count
+                    .checked_mul(size)
+                    .is_some_and(|len| len <= bytes.len())
+            })
+            .ok_or_else(|| invalid_data("truncated ppm raster"))?;
+
+        let mut samples = Vec::with_capacity(count);
+        if binary {
+            // exactly one whitespace byte separates the header from the raster
+            position += 1;
+            let raster = position
+                .checked_add(count * size)
+                .and_then(|end| bytes.get(position..end))
+                .ok_or_else(|| invalid_data("truncated ppm raster"))?;
+            for chunk in raster.chunks(size) {
+                let value = if size == 1 {
+                    chunk[0] as usize
+                } else {
+                    (chunk[0] as usize) << 8 | chunk[1] as usize
+                };
+                samples.push(value);
+            }
+        } else {
+            for _ in 0..count {
+                samples.push(
+                    next_token(bytes, &mut position)?
+                        .parse()
+                        .map_err(|_| invalid_data("bad ppm sample"))?,
+                );
+            }
+        }
+
+        let decode = |value: usize| {
+            let v = value as f64 / max_value as f64;
+            if srgb {
+                srgb_to_linear(v)
+            } else {
+                v
+            }
+        };
+        let pixels = samples
+            .chunks(3)
+            .map(|rgb| Color::new(decode(rgb[0]), decode(rgb[1]), decode(rgb[2])))
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    // plain ppm on stdout, gamma corrected
+    pub fn write_ppm(&self) {
+        println!("P3\n{} {}\n255\n", self.width, self.height);
+        for pixel in &self.pixels {
+            pixel.write();
+        }
+    }
+
+    // binary ppm, gamma corrected
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_ppm_bytes(path, Color::to_bytes)
+    }
+
+    // binary ppm of the values as they are, for images that aren't radiance
+    pub fn save_ppm_linear<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_ppm_bytes(path, Color::to_linear_bytes)
+    }
+
+    fn save_ppm_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        encode: fn(&Color) -> [u8; 3],
+    ) -> io::Result<()> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for pixel in &self.pixels {
+            bytes.extend_from_slice(&encode(pixel));
+        }
+        fs::write(path, bytes)
+    }
+}
+
+fn next_token(bytes: &[u8], position: &mut usize) -> io::Result<String> {
+    // skip whitespace and comments
+    while *position < bytes.len() {
+        match bytes[*position] {
+            b'#' => {
+                while *position < bytes.len() && bytes[*position] != b'\n' {
+                    *position += 1;
+                }
+            }
+            c if c.is_ascii_whitespace() => *position += 1,
+            _ => break,
+        }
+    }
+
+    let start = *position;
+    while *position < bytes.len() && !bytes[*position].is_ascii_whitespace() {
+        *position += 1;
+    }
+    if start == *position {
+        return Err(invalid_data("unexpected end of ppm"));
+    }
+
+    Ok(String::from_utf8_lossy(&bytes[start..*position]).into_owned())
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_binary_and_plain() {
+        let mut binary = b"P6\n2 1\n255\n".to_vec();
+        binary.extend_from_slice(&[255, 0, 51, 0, 255, 0]);
+        let plain = b"P3 # comment\n2 1\n255\n255 0 51\n0 255 0\n";
+
+        for bytes in [&binary[..], &plain[..]] {
+            let image = Image::read_ppm(bytes, false).unwrap();
+            assert_eq!((image.width, image.height), (2, 1));
+            assert_eq!(image.get(0, 0).r, 1.0);
+            assert!((image.get(0, 0).b - 0.2).abs() < 1e-12);
+            assert_eq!(image.get(1, 0).g, 1.0);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        for header in [
+            "P6\n0 0\n255\n",
+            "P6\n0 4\n255\n",
+            "P3\n4 0\n255\n",
+            "P6\n4294967296 4294967296\n255\n",
+            "P6\n18446744073709551615 18446744073709551615\n65535\n",
+            "P3\n100000 100000\n255\n",
+            "P6\n2 2\n255\n",
+        ] {
+            let mut bytes = header.as_bytes().to_vec();
+            bytes.extend_from_slice(&[0; 8]);
+            assert!(Image::read_ppm(&bytes, false).is_err(), "{header:?}");
+        }
+    }
+}
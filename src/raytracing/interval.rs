@@ -3,14 +3,16 @@ pub struct Interval {
     pub max: f64,
 }
 
-impl Interval {
-    pub fn default() -> Self {
+impl Default for Interval {
+    fn default() -> Self {
         Self {
             min: f64::INFINITY,
             max: f64::NEG_INFINITY,
         }
     }
+}
 
+impl Interval {
     pub fn size(&self) -> f64 {
         self.max - self.min
     }
@@ -0,0 +1,482 @@
+use std::{fs, io, path::Path};
+
+use super::{
+    hdr::invalid_data,
+    microfacet::refract,
+    random_float,
+    sampler::radical_inverse,
+    vec3::{dot, Vec3},
+};
+
+// traces camera rays through the actual surfaces of a lens prescription, after
+// Kolb et al. "A Realistic Camera Model" and pbrt's RealisticCamera.
+// everything in here is in millimeters, in a space with the film at z = 0 and the
+// scene towards +z
+
+#[derive(Clone, Copy)]
+pub struct LensElement {
+    // positive when the center of curvature is towards the film, 0 for the aperture stop
+    pub curvature_radius: f64,
+    // distance to the next surface towards the film
+    pub thickness: f64,
+    // of whatever fills the space behind this surface, 0 or 1 for air
+    pub refraction_index: f64,
+    pub aperture_radius: f64,
+}
+
+// the usual 50mm f/2 double gauss, US patent 2,673,491 scaled from 100mm as in pbrt.
+// radius, thickness, index and aperture diameter from the front element to the back
+pub const DOUBLE_GAUSS_50MM: &str = "
+29.475   3.76   1.67   25.2
+84.83    0.12   1      25.2
+19.275   4.025  1.67   23
+40.77    3.275  1.699  23
+12.75    5.705  1      18
+0        4.5    0      17.1
+-14.495  1.18   1.603  17
+40.77    6.065  1.658  20
+-20.385  0.19   1      20
+437.065  3.22   1.717  20
+-39.73   0      1      20
+";
+
+// film distances the exit pupil is bounded at, and rays traced for each one
+const PUPIL_SEGMENTS: usize = 64;
+const PUPIL_SAMPLES: usize = 4096;
+
+#[derive(Clone, Copy)]
+struct Bounds {
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+impl Bounds {
+    fn empty() -> Self {
+        Self {
+            min: (f64::INFINITY, f64::INFINITY),
+            max: (f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.min.0 > self.max.0 || self.min.1 > self.max.1
+    }
+
+    fn include(&mut self, x: f64, y: f64) {
+        self.min = (self.min.0.min(x), self.min.1.min(y));
+        self.max = (self.max.0.max(x), self.max.1.max(y));
+    }
+
+    fn area(&self) -> f64 {
+        if self.is_empty() {
+            0.0
+        } else {
+            (self.max.0 - self.min.0) * (self.max.1 - self.min.1)
+        }
+    }
+}
+
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+    // how big a scene unit is, to place the lens in the scene
+    pub meters_per_unit: f64,
+    film_width: f64,
+    film_height: f64,
+    exit_pupil_bounds: Vec<Bounds>,
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>) -> Self {
+        Self {
+            elements,
+            meters_per_unit: 1.0,
+            film_width: 0.0,
+            film_height: 0.0,
+            exit_pupil_bounds: vec![],
+        }
+    }
+
+    // one surface per line: radius, thickness, index and aperture diameter. # starts a comment
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut elements = vec![];
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| invalid_data("bad number in lens prescription"))?;
+            if values.len() != 4 {
+                return Err(invalid_data("lens surfaces need four values"));
+            }
+
+            elements.push(LensElement {
+                curvature_radius: values[0],
+                thickness: values[1],
+                refraction_index: values[2],
+                aperture_radius: values[3] / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err(invalid_data("empty lens prescription"));
+        }
+        Ok(Self::new(elements))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn double_gauss() -> Self {
+        Self::parse(DOUBLE_GAUSS_50MM).expect("built in prescription parses")
+    }
+
+    pub fn with_meters_per_unit(mut self, meters_per_unit: f64) -> Self {
+        self.meters_per_unit = meters_per_unit;
+        self
+    }
+
+    // moves the lens so objects at distance (mm from the film) are sharp, and bounds
+    // the exit pupil for the given film size
+    pub fn focus(&mut self, distance: f64, film_width: f64, film_height: f64) {
+        self.film_width = film_width;
+        self.film_height = film_height;
+
+        if let Some(thickness) = self.focus_thick_lens(distance) {
+            if let Some(rear) = self.elements.last_mut() {
+                rear.thickness = thickness;
+            }
+        }
+
+        let half_diagonal = film_width.hypot(film_height) / 2.0;
+        self.exit_pupil_bounds = (0..PUPIL_SEGMENTS)
+            .map(|i| {
+                let r0 = i as f64 / PUPIL_SEGMENTS as f64 * half_diagonal;
+                let r1 = (i + 1) as f64 / PUPIL_SEGMENTS as f64 * half_diagonal;
+                self.bound_exit_pupil(r0, r1)
+            })
+            .collect();
+    }
+
+    // film_point is on the film in mm, returns the ray leaving the front of the lens
+    // and its weight, which is the cos^4 and pupil size falloff relative to the center
+    pub fn sample_ray(&self, film_point: (f64, f64)) -> Option<(Vec3, Vec3, f64)> {
+        let (x, y) = film_point;
+        let r = x.hypot(y);
+        let half_diagonal = self.film_width.hypot(self.film_height) / 2.0;
+        let index = ((r / half_diagonal * PUPIL_SEGMENTS as f64) as usize)
+            .min(self.exit_pupil_bounds.len().checked_sub(1)?);
+
+        let bounds = self.exit_pupil_bounds[index];
+        if bounds.is_empty() {
+            return None;
+        }
+
+        // the bounds were found along +x, rotate them round to the film point
+        let px = bounds.min.0 + (bounds.max.0 - bounds.min.0) * random_float();
+        let py = bounds.min.1 + (bounds.max.1 - bounds.min.1) * random_float();
+        let (sin, cos) = if r > 0.0 { (y / r, x / r) } else { (0.0, 1.0) };
+        let rear = Vec3::new(cos * px - sin * py, sin * px + cos * py, self.rear_z());
+
+        let origin = Vec3::new(x, y, 0.0);
+        let direction = rear - origin;
+        let (out_origin, out_direction) = self.trace_from_film(origin, direction)?;
+
+        let cos_theta = direction.unit_vector().z;
+        let cos4_theta = cos_theta * cos_theta * cos_theta * cos_theta;
+        // relative to the on-axis pupil, or the biggest one when the lens blocks the axis
+        let reference_area = match self.exit_pupil_bounds[0].area() {
+            area if area > 0.0 => area,
+            _ => self
+                .exit_pupil_bounds
+                .iter()
+                .map(Bounds::area)
+                .fold(0.0, f64::max),
+        };
+        let weight = cos4_theta * bounds.area() / reference_area;
+        Some((out_origin, out_direction, weight))
+    }
+
+    fn rear_z(&self) -> f64 {
+        self.elements.last().map_or(0.0, |e| e.thickness)
+    }
+
+    fn front_z(&self) -> f64 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    fn rear_aperture(&self) -> f64 {
+        self.elements.last().map_or(0.0, |e| e.aperture_radius)
+    }
+
+    // in lens space the film is still at z = 0 but the lens sits at negative z
+    fn trace_from_film(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut o = Vec3::new(origin.x, origin.y, -origin.z);
+        let mut d = Vec3::new(direction.x, direction.y, -direction.z);
+
+        let mut element_z = 0.0;
+        for i in (0..self.elements.len()).rev() {
+            let element = self.elements[i];
+            element_z -= element.thickness;
+
+            let (t, normal) = intersect_element(&element, element_z, o, d)?;
+            let hit = o + d * t;
+            if hit.x * hit.x + hit.y * hit.y > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+            o = hit;
+
+            if let Some(normal) = normal {
+                let eta_i = medium_index(element.refraction_index);
+                let eta_t = if i > 0 {
+                    medium_index(self.elements[i - 1].refraction_index)
+                } else {
+                    1.0
+                };
+                d = refract(d.unit_vector() * -1.0, normal, eta_t / eta_i)?;
+            }
+        }
+
+        Some((Vec3::new(o.x, o.y, -o.z), Vec3::new(d.x, d.y, -d.z)))
+    }
+
+    fn trace_from_scene(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut o = Vec3::new(origin.x, origin.y, -origin.z);
+        let mut d = Vec3::new(direction.x, direction.y, -direction.z);
+
+        let mut element_z = -self.front_z();
+        for i in 0..self.elements.len() {
+            let element = self.elements[i];
+
+            let (t, normal) = intersect_element(&element, element_z, o, d)?;
+            let hit = o + d * t;
+            if hit.x * hit.x + hit.y * hit.y > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+            o = hit;
+
+            if let Some(normal) = normal {
+                let eta_i = if i > 0 {
+                    medium_index(self.elements[i - 1].refraction_index)
+                } else {
+                    1.0
+                };
+                let eta_t = medium_index(element.refraction_index);
+                d = refract(d.unit_vector() * -1.0, normal, eta_t / eta_i)?;
+            }
+            element_z += element.thickness;
+        }
+
+        Some((Vec3::new(o.x, o.y, -o.z), Vec3::new(d.x, d.y, -d.z)))
+    }
+
+    // principal plane and focal point on both sides of the lens, found with rays
+    // parallel to the axis
+    fn thick_lens(&self) -> Option<([f64; 2], [f64; 2])> {
+        let x = 0.001 * self.film_width.hypot(self.film_height);
+
+        let scene_in = (
+            Vec3::new(x, 0.0, self.front_z() + 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        let film_out = self.trace_from_scene(scene_in.0, scene_in.1)?;
+        let (p0, f0) = cardinal_points(scene_in, film_out)?;
+
+        let film_in = (
+            Vec3::new(x, 0.0, self.rear_z() - 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let scene_out = self.trace_from_film(film_in.0, film_in.1)?;
+        let (p1, f1) = cardinal_points(film_in, scene_out)?;
+
+        Some(([p0, p1], [f0, f1]))
+    }
+
+    // film to rear element distance that focuses at distance, from the thick lens equation
+    fn focus_thick_lens(&self, distance: f64) -> Option<f64> {
+        let (pz, fz) = self.thick_lens()?;
+        let f = fz[0] - pz[0];
+        let z = -distance;
+
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4.0 * f - pz[0]);
+        if c <= 0.0 {
+            return None;
+        }
+        let delta = 0.5 * (pz[1] - z + pz[0] - c.sqrt());
+        Some(self.rear_z() + delta)
+    }
+
+    // conservative box on the rear element that rays from film radii r0..r1 get through
+    fn bound_exit_pupil(&self, r0: f64, r1: f64) -> Bounds {
+        let extent = 1.5 * self.rear_aperture();
+        let rear_z = self.rear_z();
+
+        let mut bounds = Bounds::empty();
+        let n = PUPIL_SAMPLES;
+        let side = (n as f64).sqrt() as usize;
+        for i in 0..n {
+            // a fixed sequence rather than random numbers, so every render and both
+            // eyes of a stereo rig get the same bounds
+            let u = radical_inverse(i as u32, 2);
+            let film = Vec3::new(r0 + u * (r1 - r0), 0.0, 0.0);
+            let rear = Vec3::new(
+                -extent + ((i % side) as f64 + 0.5) / side as f64 * 2.0 * extent,
+                -extent + ((i / side) as f64 + 0.5) / side as f64 * 2.0 * extent,
+                rear_z,
+            );
+
+            if self.trace_from_film(film, rear - film).is_some() {
+                bounds.include(rear.x, rear.y);
+            }
+        }
+
+        if bounds.is_empty() {
+            return bounds;
+        }
+
+        // pad by a grid cell so the sparse samples don't clip the real pupil
+        let pad = 2.0 * extent / side as f64;
+        bounds.min = (bounds.min.0 - pad, bounds.min.1 - pad);
+        bounds.max = (bounds.max.0 + pad, bounds.max.1 + pad);
+        bounds
+    }
+}
+
+// air and the aperture stop are both written down as 0
+fn medium_index(refraction_index: f64) -> f64 {
+    if refraction_index == 0.0 {
+        1.0
+    } else {
+        refraction_index
+    }
+}
+
+// returns the distance along d and, for glass, the surface normal facing back along d
+fn intersect_element(
+    element: &LensElement,
+    element_z: f64,
+    o: Vec3,
+    d: Vec3,
+) -> Option<(f64, Option<Vec3>)> {
+    if element.curvature_radius == 0.0 {
+        if d.z == 0.0 {
+            return None;
+        }
+        return Some(((element_z - o.z) / d.z, None));
+    }
+
+    let radius = element.curvature_radius;
+    let center = Vec3::new(0.0, 0.0, element_z + radius);
+    let oc = o - center;
+
+    let a = d.length_squared();
+    let b = 2.0 * dot(d, oc);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    let t0 = (-b - sqrtd) / (2.0 * a);
+    let t1 = (-b + sqrtd) / (2.0 * a);
+
+    // the lens surface is the cap of the sphere nearest the axis crossing
+    let use_closer = (d.z > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+    if t < 0.0 {
+        return None;
+    }
+
+    let normal = (oc + d * t).unit_vector();
+    let normal = if dot(normal, d) > 0.0 {
+        normal * -1.0
+    } else {
+        normal
+    };
+    Some((t, Some(normal)))
+}
+
+// where a ray parallel to the axis at height x crosses the axis (focal point) and
+// where its extension meets the outgoing ray (principal plane), as z distances
+fn cardinal_points(ray_in: (Vec3, Vec3), ray_out: (Vec3, Vec3)) -> Option<(f64, f64)> {
+    let (in_origin, _) = ray_in;
+    let (out_origin, out_direction) = ray_out;
+    if out_direction.x == 0.0 {
+        return None;
+    }
+
+    let tf = -out_origin.x / out_direction.x;
+    let focal_z = -(out_origin + out_direction * tf).z;
+    let tp = (in_origin.x - out_origin.x) / out_direction.x;
+    let principal_z = -(out_origin + out_direction * tp).z;
+    Some((principal_z, focal_z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // where a ray leaving the back of the lens crosses the axis, in mm from the film
+    fn axis_crossing(ray: (Vec3, Vec3)) -> f64 {
+        let (origin, direction) = ray;
+        (origin + direction * (-origin.x / direction.x)).z
+    }
+
+    #[test]
+    fn double_gauss_is_a_50mm_lens() {
+        let mut lens = LensSystem::double_gauss();
+        lens.focus(1e6, 36.0, 24.0);
+
+        let ([p0, _], [f0, _]) = lens.thick_lens().unwrap();
+        let focal_length = f0 - p0;
+        assert!((focal_length - 50.0).abs() < 1.0, "{focal_length}");
+    }
+
+    // a paraxial ray from an on-axis point at the focus distance has to come to a
+    // point on the film, wherever the lens was focused
+    #[test]
+    fn paraxial_rays_focus_on_the_film() {
+        for distance in [500.0, 1000.0, 5000.0] {
+            let mut lens = LensSystem::double_gauss();
+            lens.focus(distance, 36.0, 24.0);
+
+            let object = Vec3::new(0.0, 0.0, distance);
+            let front = Vec3::new(0.05, 0.0, lens.front_z());
+            let ray = lens.trace_from_scene(object, front - object).unwrap();
+            let image = axis_crossing(ray);
+            assert!(
+                image.abs() < 0.01,
+                "focused at {distance} lands {image} off the film"
+            );
+        }
+
+        // focused at infinity the film sits at the rear focal point
+        let mut lens = LensSystem::double_gauss();
+        lens.focus(1e9, 36.0, 24.0);
+        let ray = lens
+            .trace_from_scene(
+                Vec3::new(0.05, 0.0, lens.front_z() + 1.0),
+                Vec3::new(0.0, 0.0, -1.0),
+            )
+            .unwrap();
+        assert!(axis_crossing(ray).abs() < 0.01);
+    }
+
+    #[test]
+    fn focusing_closer_moves_the_lens_out() {
+        let mut far = LensSystem::double_gauss();
+        far.focus(10_000.0, 36.0, 24.0);
+        let mut near = LensSystem::double_gauss();
+        near.focus(500.0, 36.0, 24.0);
+
+        // a thin 50mm lens focused at 500mm needs about 5.6mm more extension
+        let extension = near.rear_z() - far.rear_z();
+        assert!(extension > 4.0 && extension < 7.0, "{extension}");
+    }
+}
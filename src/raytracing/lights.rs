@@ -0,0 +1,166 @@
+use super::{
+    color::Color,
+    degrees_to_radians,
+    spectrum::Illuminant,
+    vec3::{dot, Point, Vec3},
+};
+
+pub struct LightSample {
+    // unit direction from the shaded point towards the light
+    pub direction: Vec3,
+    // incident radiance at the shaded point, falloff already applied
+    pub radiance: Color,
+    pub distance: f64,
+    // spectrum the radiance is a tint of, only resolved in spectral mode
+    pub illuminant: Illuminant,
+}
+
+// delta lights can't be hit by rays, they only contribute through shadow rays
+pub trait Light: Send + Sync {
+    fn sample(&self, point: Point) -> Option<LightSample>;
+}
+
+pub struct PointLight {
+    pub position: Point,
+    pub intensity: Color,
+    pub illuminant: Illuminant,
+}
+
+impl PointLight {
+    pub fn new(position: Point, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+            illuminant: Illuminant::d65(),
+        }
+    }
+
+    pub fn with_illuminant(mut self, illuminant: Illuminant) -> Self {
+        self.illuminant = illuminant;
+        self
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Point) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            direction: to_light / distance,
+            radiance: self.intensity * (1.0 / distance_squared),
+            distance,
+            illuminant: self.illuminant,
+        })
+    }
+}
+
+pub struct SpotLight {
+    pub position: Point,
+    pub direction: Vec3,
+    pub intensity: Color,
+    pub illuminant: Illuminant,
+    cos_cone: f64,
+    cos_falloff_start: f64,
+}
+
+impl SpotLight {
+    // angles are in degrees, measured from the spot axis to the edge of the cone
+    pub fn new(
+        position: Point,
+        direction: Vec3,
+        intensity: Color,
+        cone_angle: f64,
+        falloff_start: f64,
+    ) -> Self {
+        let falloff_start = falloff_start.min(cone_angle);
+
+        Self {
+            position,
+            direction: direction.unit_vector(),
+            intensity,
+            illuminant: Illuminant::d65(),
+            cos_cone: degrees_to_radians(cone_angle).cos(),
+            cos_falloff_start: degrees_to_radians(falloff_start).cos(),
+        }
+    }
+
+    pub fn with_illuminant(mut self, illuminant: Illuminant) -> Self {
+        self.illuminant = illuminant;
+        self
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_cone {
+            return 0.0;
+        }
+
+        // smoothstep between the edge of the cone and the start of the falloff
+        let t = (cos_theta - self.cos_cone) / (self.cos_falloff_start - self.cos_cone);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Point) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+        let falloff = self.falloff(dot(direction * -1.0, self.direction));
+        if falloff == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            radiance: self.intensity * (falloff / distance_squared),
+            distance,
+            illuminant: self.illuminant,
+        })
+    }
+}
+
+pub struct DirectionalLight {
+    // the direction the light travels in
+    pub direction: Vec3,
+    pub irradiance: Color,
+    pub illuminant: Illuminant,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color) -> Self {
+        Self {
+            direction: direction.unit_vector(),
+            irradiance,
+            illuminant: Illuminant::d65(),
+        }
+    }
+
+    pub fn with_illuminant(mut self, illuminant: Illuminant) -> Self {
+        self.illuminant = illuminant;
+        self
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Point) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.direction * -1.0,
+            radiance: self.irradiance,
+            distance: f64::INFINITY,
+            illuminant: self.illuminant,
+        })
+    }
+}
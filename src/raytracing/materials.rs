@@ -1,18 +1,54 @@
+use std::sync::Arc;
+
 use super::{
     color::Color,
+    fresnel::{self, ThinFilm},
     hittable::HitRecord,
+    medium::Medium,
+    microfacet::{self, Ggx},
+    onb::Onb,
     random_float,
-    ray::{self, Ray},
-    vec3::{dot, Vec3},
+    ray::Ray,
+    spectrum::SampledWavelengths,
+    texture::Texture,
+    vec3::{cross, dot, Vec3},
+    PI,
 };
 
-pub trait Material {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &mut Ray) -> bool {
+pub trait Material: Send + Sync {
+    fn scatter(&self, _ray_in: &Ray, _hit_record: &HitRecord, _scattered: &mut Ray) -> bool {
         false
     }
 
-    fn attenuation(&self) -> Color;
+    // bsdf times cosine for a given outgoing direction, used when sampling lights
+    fn eval(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // pdf of scatter() choosing a direction, zero for perfectly specular materials
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    // throughput of the ray chosen by scatter()
+    fn attenuation(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color;
     fn clone_box(&self) -> Box<dyn Material>;
+
+    // normal used for shading, facing the same way as hit_record.normal
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        hit_record.normal
+    }
+
+    // false where the surface is cut out, shapes call this before accepting a hit so
+    // rays (shadow rays included) carry on to whatever is behind
+    fn alpha_test(&self, _hit_record: &HitRecord) -> bool {
+        true
+    }
+
+    // medium filling a closed surface, entered by rays transmitted through a front face
+    fn interior(&self) -> Option<Medium> {
+        None
+    }
 }
 
 pub struct Lambertian {
@@ -38,7 +74,20 @@ impl Material for Lambertian {
         true
     }
 
-    fn attenuation(&self) -> Color {
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Color {
+        self.albedo * self.pdf(ray_in, hit_record, direction)
+    }
+
+    fn pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        let cosine = dot(direction.unit_vector(), hit_record.normal);
+        if cosine > 0.0 {
+            cosine / PI
+        } else {
+            0.0
+        }
+    }
+
+    fn attenuation(&self, _ray_in: &Ray, _hit_record: &HitRecord, _scattered: &Ray) -> Color {
         self.albedo
     }
 
@@ -81,7 +130,7 @@ impl Material for Metal {
         dot(scattered.direction, hit_record.normal) > 0.0
     }
 
-    fn attenuation(&self) -> Color {
+    fn attenuation(&self, _ray_in: &Ray, _hit_record: &HitRecord, _scattered: &Ray) -> Color {
         self.albedo
     }
 
@@ -94,13 +143,164 @@ impl Material for Metal {
     }
 }
 
+// wavelength dependent index of refraction, wavelengths in nanometers
+#[derive(Clone, Copy)]
+pub enum Dispersion {
+    // n = a + b / lambda^2, with lambda in micrometers
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i)), with lambda in micrometers
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030625, 0.011236, 0.0],
+    };
+
+    pub const DENSE_FLINT: Dispersion = Dispersion::Sellmeier {
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+
+    pub fn refraction_index(&self, wavelength: f64) -> f64 {
+        let micrometers = wavelength / 1000.0;
+        let l2 = micrometers * micrometers;
+
+        match *self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.sqrt()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct Dielectric {
     pub refraction_index: f64,
+    // beer-lambert absorption coefficient per unit length travelled inside
+    pub absorption: Color,
+    pub dispersion: Option<Dispersion>,
+    pub thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self {
+            refraction_index,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            dispersion: None,
+            thin_film: None,
+        }
+    }
+
+    // refraction_index is kept at the sodium d line for anything that ignores wavelength
+    pub fn dispersive(dispersion: Dispersion) -> Self {
+        Self {
+            refraction_index: dispersion.refraction_index(587.6),
+            absorption: Color::new(0.0, 0.0, 0.0),
+            dispersion: Some(dispersion),
+            thin_film: None,
+        }
+    }
+
+    // glass that transmits `color` after travelling `distance` through it. a zero
+    // distance would make the absorption infinite, it's clamped like a mean free path
+    pub fn tinted(refraction_index: f64, color: Color, distance: f64) -> Self {
+        let distance = distance.max(1e-6);
+        let coefficient = |c: f64| -c.clamp(1e-6, 1.0).ln() / distance;
+
+        Self {
+            refraction_index,
+            absorption: Color::new(
+                coefficient(color.r),
+                coefficient(color.g),
+                coefficient(color.b),
+            ),
+            dispersion: None,
+            thin_film: None,
+        }
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    fn wavelength_refraction_index(&self, wavelengths: &SampledWavelengths) -> f64 {
+        match self.dispersion {
+            Some(dispersion) => dispersion.refraction_index(wavelengths.primary()),
+            None => self.refraction_index,
+        }
+    }
+
+    // per channel reflectance through the film, which sits on the outside of the glass
+    fn film_reflectance(
+        thin_film: &ThinFilm,
+        cos_theta: f64,
+        front_face: bool,
+        refraction_index: f64,
+    ) -> Color {
+        let (incident, substrate) = if front_face {
+            (1.0, refraction_index)
+        } else {
+            (refraction_index, 1.0)
+        };
+        let eta = Color::new(substrate, substrate, substrate);
+        let k = Color::new(0.0, 0.0, 0.0);
+
+        thin_film.reflectance_rgb(cos_theta, incident, eta, k)
+    }
+
+    // per channel reflectance and the probability of picking reflection, shared by
+    // scatter and attenuation so the lobe choice and its weight always agree
+    fn lobe_reflectance(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> (Color, f64) {
+        let refraction_index = self.wavelength_refraction_index(wavelengths);
+        let ri = if hit_record.front_face {
+            1.0 / refraction_index
+        } else {
+            refraction_index
+        };
+
+        let cos_theta =
+            dot(ray_in.direction.unit_vector() * -1.0, hit_record.normal).clamp(0.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        if ri * sin_theta > 1.0 {
+            // total internal reflection, whatever the film does
+            return (Color::new(1.0, 1.0, 1.0), 1.0);
+        }
+
+        match &self.thin_film {
+            Some(thin_film) => {
+                let reflectance = Self::film_reflectance(
+                    thin_film,
+                    cos_theta,
+                    hit_record.front_face,
+                    refraction_index,
+                );
+                let probability = (reflectance.r + reflectance.g + reflectance.b) / 3.0;
+                (reflectance, probability)
+            }
+            None => {
+                let reflectance = Self::reflectance(cos_theta, ri);
+                (
+                    Color::new(reflectance, reflectance, reflectance),
+                    reflectance,
+                )
+            }
+        }
     }
 
     fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
@@ -112,19 +312,22 @@ impl Dielectric {
 
 impl Material for Dielectric {
     fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &mut Ray) -> bool {
+        if self.dispersion.is_some() {
+            scattered.wavelengths.terminate_secondary();
+        }
+        let refraction_index = self.wavelength_refraction_index(&scattered.wavelengths);
+
         let ri: f64 = if hit_record.front_face {
-            1.0 / self.refraction_index
+            1.0 / refraction_index
         } else {
-            self.refraction_index
+            refraction_index
         };
 
         let unit_direction = ray_in.direction.unit_vector();
-        let cos_theta = dot(unit_direction * -1.0, hit_record.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let (_, reflect_probability) =
+            self.lobe_reflectance(ray_in, hit_record, &scattered.wavelengths);
 
-        let cannot_refract = ri * sin_theta > 1.0;
-
-        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > random_float() {
+        let direction = if reflect_probability > random_float() {
             unit_direction.reflect(hit_record.normal)
         } else {
             unit_direction.refract(hit_record.normal, ri)
@@ -136,12 +339,1213 @@ impl Material for Dielectric {
         true
     }
 
-    fn attenuation(&self) -> Color {
-        Color::new(1.0, 1.0, 1.0)
+    fn attenuation(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        // the film tints reflection and transmission differently, but the lobe was
+        // picked with the average so only the per channel ratio is left
+        let film = if self.thin_film.is_some() {
+            let (reflectance, probability) =
+                self.lobe_reflectance(ray_in, hit_record, &scattered.wavelengths);
+
+            if dot(scattered.direction, hit_record.normal) > 0.0 {
+                reflectance * (1.0 / probability.max(1e-6))
+            } else {
+                Color::new(
+                    1.0 - reflectance.r,
+                    1.0 - reflectance.g,
+                    1.0 - reflectance.b,
+                ) * (1.0 / (1.0 - probability).max(1e-6))
+            }
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        };
+
+        if hit_record.front_face {
+            return film;
+        }
+
+        // hitting the inside means the ray has travelled through the glass since it entered
+        let distance = hit_record.t * ray_in.direction.length();
+        film * Color::new(
+            (-self.absorption.r * distance).exp(),
+            (-self.absorption.g * distance).exp(),
+            (-self.absorption.b * distance).exp(),
+        )
+    }
+
+    fn clone_box(&self) -> Box<dyn Material> {
+        let material = Dielectric {
+            refraction_index: self.refraction_index,
+            absorption: self.absorption,
+            dispersion: self.dispersion,
+            thin_film: self.thin_film,
+        };
+        Box::new(material)
+    }
+}
+
+// rough metal with a GGX microfacet distribution and per channel complex fresnel.
+// anisotropy stretches the highlight along the surface tangent (dp/du)
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub distribution: Ggx,
+    pub thin_film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64, anisotropy: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: Ggx::from_roughness(roughness, anisotropy),
+            thin_film: None,
+        }
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    fn fresnel(&self, cos_theta: f64) -> Color {
+        match &self.thin_film {
+            Some(thin_film) => thin_film.reflectance_rgb(cos_theta, 1.0, self.eta, self.k),
+            None => fresnel::conductor_rgb(cos_theta, self.eta, self.k),
+        }
+    }
+
+    // measured indices sampled at 650, 550 and 450nm
+    pub fn gold(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+            0.0,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+            0.0,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+            0.0,
+        )
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.146),
+            roughness,
+            0.0,
+        )
+    }
+
+    fn local_directions(
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        direction: Vec3,
+    ) -> (Onb, Vec3, Vec3) {
+        let frame = Onb::from_tangent(hit_record.normal, hit_record.tangent);
+        let wo = frame.to_local(ray_in.direction.unit_vector() * -1.0);
+        let wi = frame.to_local(direction.unit_vector());
+        (frame, wo, wi)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &mut Ray) -> bool {
+        let frame = Onb::from_tangent(hit_record.normal, hit_record.tangent);
+        let wo = frame.to_local(ray_in.direction.unit_vector() * -1.0);
+        if wo.z <= 0.0 {
+            return false;
+        }
+
+        let wi = if self.distribution.is_smooth() {
+            Vec3::new(-wo.x, -wo.y, wo.z)
+        } else {
+            let wm = self
+                .distribution
+                .sample_visible_normal(wo, random_float(), random_float());
+            (wo * -1.0).reflect(wm)
+        };
+        if wi.z <= 0.0 {
+            return false;
+        }
+
+        scattered.origin = hit_record.point;
+        scattered.direction = frame.transform(wi);
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Color {
+        let (_, wo, wi) = Self::local_directions(ray_in, hit_record, direction);
+        if self.distribution.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let wm = (wo + wi).unit_vector();
+        let fresnel = self.fresnel(dot(wo, wm));
+        let f = self.distribution.d(wm) * self.distribution.g(wo, wi) / (4.0 * wo.z);
+
+        fresnel * f
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        let (_, wo, wi) = Self::local_directions(ray_in, hit_record, direction);
+        if self.distribution.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let wm = (wo + wi).unit_vector();
+        self.distribution.visible_d(wo, wm) / (4.0 * dot(wo, wm).abs())
+    }
+
+    fn attenuation(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        let (_, wo, wi) = Self::local_directions(ray_in, hit_record, scattered.direction);
+        let wm = (wo + wi).unit_vector();
+        let fresnel = self.fresnel(dot(wo, wm));
+
+        if self.distribution.is_smooth() {
+            return fresnel;
+        }
+
+        // eval / pdf with the visible normal pdf, most of the terms cancel
+        fresnel * (self.distribution.g(wo, wi) / self.distribution.g1(wo))
+    }
+
+    fn clone_box(&self) -> Box<dyn Material> {
+        let material = Conductor {
+            eta: self.eta,
+            k: self.k,
+            distribution: self.distribution,
+            thin_film: self.thin_film,
+        };
+        Box::new(material)
+    }
+}
+
+// frosted glass, Walter et al. "Microfacet Models for Refraction through Rough Surfaces"
+// https://www.graphics.cornell.edu/~bjw/microfacetbsdf.pdf. the frame follows the
+// surface tangent like Conductor's, so an anisotropic distribution stretches along it
+pub struct RoughDielectric {
+    pub refraction_index: f64,
+    pub distribution: Ggx,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index: f64, roughness: f64) -> Self {
+        Self {
+            refraction_index,
+            distribution: Ggx::from_roughness(roughness, 0.0),
+        }
+    }
+
+    // index on the far side of the surface over the index on the ray's side
+    fn relative_eta(&self, hit_record: &HitRecord) -> f64 {
+        if hit_record.front_face {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        }
+    }
+
+    // generalized half vector, facing the same side as the shading normal
+    fn half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+        let reflect = wi.z > 0.0;
+        let wm = if reflect { wo + wi } else { wi * eta + wo };
+        if wm.near_zero() {
+            return None;
+        }
+
+        let mut wm = wm.unit_vector();
+        if wm.z < 0.0 {
+            wm = wm * -1.0;
+        }
+
+        // discard backfacing microfacets
+        if dot(wm, wi) * wi.z < 0.0 || dot(wm, wo) * wo.z < 0.0 {
+            return None;
+        }
+        Some(wm)
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &mut Ray) -> bool {
+        let eta = self.relative_eta(hit_record);
+        let frame = Onb::from_tangent(hit_record.normal, hit_record.tangent);
+        let wo = frame.to_local(ray_in.direction.unit_vector() * -1.0);
+        if wo.z <= 0.0 {
+            return false;
+        }
+
+        let wm = if self.distribution.is_smooth() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            self.distribution
+                .sample_visible_normal(wo, random_float(), random_float())
+        };
+
+        let reflectance = fresnel::dielectric(dot(wo, wm), eta);
+        let wi = if random_float() < reflectance {
+            (wo * -1.0).reflect(wm)
+        } else {
+            match microfacet::refract(wo, wm, eta) {
+                Some(wi) => wi,
+                None => return false,
+            }
+        };
+
+        // the sampled facet can send the ray to the wrong side of the macro surface
+        let reflected = dot(wo, wm) > 0.0 && dot(wi, wm) > 0.0 && wi.z > 0.0;
+        let transmitted = wi.z < 0.0 && dot(wi, wm) < 0.0;
+        if !reflected && !transmitted {
+            return false;
+        }
+
+        scattered.origin = hit_record.point;
+        scattered.direction = frame.transform(wi);
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if self.distribution.is_smooth() {
+            return black;
+        }
+
+        let eta = self.relative_eta(hit_record);
+        let frame = Onb::from_tangent(hit_record.normal, hit_record.tangent);
+        let wo = frame.to_local(ray_in.direction.unit_vector() * -1.0);
+        let wi = frame.to_local(direction.unit_vector());
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return black;
+        }
+
+        let Some(wm) = Self::half_vector(wo, wi, eta) else {
+            return black;
+        };
+
+        let reflectance = fresnel::dielectric(dot(wo, wm), eta);
+        let d = self.distribution.d(wm);
+        let g = self.distribution.g(wo, wi);
+
+        let f = if wi.z > 0.0 {
+            d * g * reflectance / (4.0 * wo.z)
+        } else {
+            let denom = dot(wi, wm) + dot(wo, wm) / eta;
+            let denom = denom * denom;
+            // radiance is compressed by eta^2 when crossing into the denser medium
+            d * g * (1.0 - reflectance) * (dot(wi, wm) * dot(wo, wm) / (wo.z * denom)).abs()
+                / (eta * eta)
+        };
+
+        Color::new(f, f, f)
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+
+        let eta = self.relative_eta(hit_record);
+        let frame = Onb::from_tangent(hit_record.normal, hit_record.tangent);
+        let wo = frame.to_local(ray_in.direction.unit_vector() * -1.0);
+        let wi = frame.to_local(direction.unit_vector());
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+
+        let Some(wm) = Self::half_vector(wo, wi, eta) else {
+            return 0.0;
+        };
+
+        let reflectance = fresnel::dielectric(dot(wo, wm), eta);
+        let visible = self.distribution.visible_d(wo, wm);
+
+        if wi.z > 0.0 {
+            visible / (4.0 * dot(wo, wm).abs()) * reflectance
+        } else {
+            let denom = dot(wi, wm) + dot(wo, wm) / eta;
+            let dwm_dwi = dot(wi, wm).abs() / (denom * denom);
+            visible * dwm_dwi * (1.0 - reflectance)
+        }
+    }
+
+    fn attenuation(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        let eta = self.relative_eta(hit_record);
+        let frame = Onb::from_tangent(hit_record.normal, hit_record.tangent);
+        let wo = frame.to_local(ray_in.direction.unit_vector() * -1.0);
+        let wi = frame.to_local(scattered.direction.unit_vector());
+
+        // fresnel was used to pick the lobe, so only shadowing and the eta^2 scaling remain
+        let shadowing = if self.distribution.is_smooth() {
+            1.0
+        } else {
+            self.distribution.g(wo, wi) / self.distribution.g1(wo)
+        };
+        let scale = if wi.z > 0.0 {
+            shadowing
+        } else {
+            shadowing / (eta * eta)
+        };
+
+        Color::new(scale, scale, scale)
+    }
+
+    fn clone_box(&self) -> Box<dyn Material> {
+        let material = RoughDielectric {
+            refraction_index: self.refraction_index,
+            distribution: self.distribution,
+        };
+        Box::new(material)
+    }
+}
+
+// disney style uber material, Burley "Physically Based Shading at Disney"
+// https://media.disneyanimation.com/uploads/production/publication_asset/48/asset/s2012_pbs_disney_brdf_notes_v3.pdf
+// every lobe is sampled and evaluated from the same weights, so attenuation is always eval / pdf
+#[derive(Clone, Copy)]
+pub struct Principled {
+    pub base_color: Color,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub anisotropy: f64,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub transmission: f64,
+    pub refraction_index: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: Color::new(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            anisotropy: 0.0,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.1,
+            transmission: 0.0,
+            refraction_index: 1.5,
+        }
+    }
+}
+
+// keeps every lobe away from a delta distribution, which one-sample mis can't mix
+const PRINCIPLED_MIN_ALPHA: f64 = 2e-3;
+
+impl Principled {
+    pub fn new(base_color: Color, metallic: f64, roughness: f64) -> Self {
+        Self {
+            base_color,
+            metallic,
+            roughness,
+            ..Default::default()
+        }
+    }
+
+    fn diffuse_weight(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    fn transmission_weight(&self) -> f64 {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    fn specular_distribution(&self) -> Ggx {
+        let d = Ggx::from_roughness(self.roughness, self.anisotropy);
+        Ggx::new(
+            d.alpha_x.max(PRINCIPLED_MIN_ALPHA),
+            d.alpha_y.max(PRINCIPLED_MIN_ALPHA),
+        )
+    }
+
+    fn clearcoat_distribution(&self) -> Ggx {
+        let d = Ggx::from_roughness(self.clearcoat_roughness, 0.0);
+        Ggx::new(
+            d.alpha_x.max(PRINCIPLED_MIN_ALPHA),
+            d.alpha_y.max(PRINCIPLED_MIN_ALPHA),
+        )
+    }
+
+    fn glass(&self) -> RoughDielectric {
+        RoughDielectric {
+            refraction_index: self.refraction_index,
+            distribution: self.specular_distribution(),
+        }
+    }
+
+    fn tint(&self) -> Color {
+        let luminance = self.base_color.luminance();
+        if luminance > 0.0 {
+            self.base_color * (1.0 / luminance)
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    fn specular_f0(&self) -> Color {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let dielectric = lerp(white, self.tint(), self.specular_tint) * (0.08 * self.specular);
+        lerp(dielectric, self.base_color, self.metallic)
+    }
+
+    // probabilities of sampling the diffuse, specular, clearcoat and transmission lobes
+    fn lobe_probabilities(&self) -> [f64; 4] {
+        let weights = [
+            self.diffuse_weight(),
+            1.0 - self.transmission_weight(),
+            0.25 * self.clearcoat,
+            self.transmission_weight(),
+        ];
+        let total: f64 = weights.iter().sum();
+        weights.map(|w| w / total)
+    }
+
+    fn reflection_eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return black;
+        }
+
+        let wh = (wo + wi).unit_vector();
+        let cos_d = dot(wi, wh);
+        let mut base = black;
+
+        let diffuse_weight = self.diffuse_weight();
+        if diffuse_weight > 0.0 {
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+            let diffuse = self.base_color * (retro / PI);
+
+            let white = Color::new(1.0, 1.0, 1.0);
+            let sheen =
+                lerp(white, self.tint(), self.sheen_tint) * (self.sheen * schlick_weight(cos_d));
+
+            base += (diffuse + sheen) * (diffuse_weight * wi.z);
+        }
+
+        let specular = self.specular_distribution();
+        let fresnel = lerp(
+            self.specular_f0(),
+            Color::new(1.0, 1.0, 1.0),
+            schlick_weight(cos_d),
+        );
+        base += fresnel
+            * (specular.d(wh) * specular.g(wo, wi) / (4.0 * wo.z)
+                * (1.0 - self.transmission_weight()));
+
+        // the clearcoat sits on top and takes its fresnel share from everything beneath it
+        let layer = 1.0 - self.clearcoat * schlick(0.04, wo.z);
+        let clearcoat = self.clearcoat_distribution();
+        let coat =
+            0.25 * self.clearcoat * schlick(0.04, cos_d) * clearcoat.d(wh) * clearcoat.g(wo, wi)
+                / (4.0 * wo.z);
+
+        base * layer + Color::new(coat, coat, coat)
+    }
+
+    fn reflection_pdf(distribution: &Ggx, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wh = (wo + wi).unit_vector();
+        distribution.visible_d(wo, wh) / (4.0 * dot(wo, wh).abs())
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &mut Ray) -> bool {
+        let frame = Onb::from_tangent(hit_record.normal, hit_record.tangent);
+        let wo = frame.to_local(ray_in.direction.unit_vector() * -1.0);
+        if wo.z <= 0.0 {
+            return false;
+        }
+
+        let [diffuse, specular, clearcoat, _] = self.lobe_probabilities();
+        let u = random_float();
+
+        let wi = if u < diffuse {
+            let mut local = Vec3::new(0.0, 0.0, 1.0) + Vec3::random_unit_vector();
+            if local.near_zero() {
+                local = Vec3::new(0.0, 0.0, 1.0)
+            }
+            local.unit_vector()
+        } else if u < diffuse + specular + clearcoat {
+            let distribution = if u < diffuse + specular {
+                self.specular_distribution()
+            } else {
+                self.clearcoat_distribution()
+            };
+            let wm = distribution.sample_visible_normal(wo, random_float(), random_float());
+            (wo * -1.0).reflect(wm)
+        } else {
+            return self.glass().scatter(ray_in, hit_record, scattered);
+        };
+
+        if wi.z <= 0.0 {
+            return false;
+        }
+
+        scattered.origin = hit_record.point;
+        scattered.direction = frame.transform(wi);
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Color {
+        let frame = Onb::from_tangent(hit_record.normal, hit_record.tangent);
+        let wo = frame.to_local(ray_in.direction.unit_vector() * -1.0);
+        let wi = frame.to_local(direction.unit_vector());
+
+        let mut f = self.reflection_eval(wo, wi);
+
+        let transmission_weight = self.transmission_weight();
+        if transmission_weight > 0.0 {
+            let layer = 1.0 - self.clearcoat * schlick(0.04, wo.z);
+            f += self.glass().eval(ray_in, hit_record, direction)
+                * self.base_color
+                * (transmission_weight * layer);
+        }
+
+        f
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        let frame = Onb::from_tangent(hit_record.normal, hit_record.tangent);
+        let wo = frame.to_local(ray_in.direction.unit_vector() * -1.0);
+        let wi = frame.to_local(direction.unit_vector());
+
+        let [diffuse, specular, clearcoat, transmission] = self.lobe_probabilities();
+        let mut pdf = 0.0;
+
+        if wo.z > 0.0 && wi.z > 0.0 {
+            pdf += diffuse * wi.z / PI;
+            pdf += specular * Self::reflection_pdf(&self.specular_distribution(), wo, wi);
+            pdf += clearcoat * Self::reflection_pdf(&self.clearcoat_distribution(), wo, wi);
+        }
+        if transmission > 0.0 {
+            pdf += transmission * self.glass().pdf(ray_in, hit_record, direction);
+        }
+
+        pdf
+    }
+
+    fn attenuation(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        let pdf = self.pdf(ray_in, hit_record, scattered.direction);
+        if pdf <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        self.eval(ray_in, hit_record, scattered.direction) * (1.0 / pdf)
     }
 
     fn clone_box(&self) -> Box<dyn Material> {
-        let material = Dielectric::new(self.refraction_index);
+        Box::new(*self)
+    }
+}
+
+fn lerp(a: Color, b: Color, t: f64) -> Color {
+    (a * (1.0 - t)) + (b * t)
+}
+
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+fn schlick(f0: f64, cosine: f64) -> f64 {
+    f0 + (1.0 - f0) * schlick_weight(cosine)
+}
+
+pub enum SurfaceDetail {
+    // tangent space normals, encoded as rgb = (n + 1) / 2
+    NormalMap(Arc<dyn Texture>),
+    // heights in the texture's luminance, scale is height per unit of uv
+    BumpMap {
+        height: Arc<dyn Texture>,
+        scale: f64,
+    },
+}
+
+// wraps any material and perturbs its shading normal, the geometric normal in
+// the hit record is left alone so the integrator can guard against leaks
+pub struct NormalMapped {
+    pub material: Box<dyn Material>,
+    pub detail: SurfaceDetail,
+}
+
+impl NormalMapped {
+    pub fn normal_map(material: Box<dyn Material>, texture: Arc<dyn Texture>) -> Self {
+        Self {
+            material,
+            detail: SurfaceDetail::NormalMap(texture),
+        }
+    }
+
+    pub fn bump_map(material: Box<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        Self {
+            material,
+            detail: SurfaceDetail::BumpMap { height, scale },
+        }
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &mut Ray) -> bool {
+        self.material.scatter(ray_in, hit_record, scattered)
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Color {
+        self.material.eval(ray_in, hit_record, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        self.material.pdf(ray_in, hit_record, direction)
+    }
+
+    fn attenuation(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        self.material.attenuation(ray_in, hit_record, scattered)
+    }
+
+    fn clone_box(&self) -> Box<dyn Material> {
+        let detail = match &self.detail {
+            SurfaceDetail::NormalMap(texture) => SurfaceDetail::NormalMap(texture.clone()),
+            SurfaceDetail::BumpMap { height, scale } => SurfaceDetail::BumpMap {
+                height: height.clone(),
+                scale: *scale,
+            },
+        };
+        let material = NormalMapped {
+            material: self.material.clone(),
+            detail,
+        };
         Box::new(material)
     }
+
+    fn alpha_test(&self, hit_record: &HitRecord) -> bool {
+        self.material.alpha_test(hit_record)
+    }
+
+    fn interior(&self) -> Option<Medium> {
+        self.material.interior()
+    }
+
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        let normal = hit_record.outward_normal();
+
+        // gram-schmidt the tangent against the normal in case the surface stretched it
+        let tangent = hit_record.tangent - normal * dot(hit_record.tangent, normal);
+        if tangent.near_zero() {
+            return hit_record.normal;
+        }
+        let tangent = tangent.unit_vector();
+        let bitangent = cross(normal, tangent);
+
+        let perturbed = match &self.detail {
+            SurfaceDetail::NormalMap(texture) => {
+                let encoded = texture.value(hit_record.u, hit_record.v, hit_record.point);
+                (tangent * (2.0 * encoded.r - 1.0))
+                    + (bitangent * (2.0 * encoded.g - 1.0))
+                    + (normal * (2.0 * encoded.b - 1.0))
+            }
+            SurfaceDetail::BumpMap { height, scale } => {
+                const DELTA: f64 = 1e-3;
+                let (u, v, p) = (hit_record.u, hit_record.v, hit_record.point);
+                let h = height.value(u, v, p).luminance();
+                let dh_du = (height.value(u + DELTA, v, p).luminance() - h) / DELTA;
+                let dh_dv = (height.value(u, v + DELTA, p).luminance() - h) / DELTA;
+
+                normal - (tangent * (scale * dh_du)) - (bitangent * (scale * dh_dv))
+            }
+        };
+        if perturbed.near_zero() {
+            return hit_record.normal;
+        }
+
+        let perturbed = perturbed.unit_vector();
+        if hit_record.front_face {
+            perturbed
+        } else {
+            perturbed * -1.0
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum AlphaMode {
+    // hard cutout, alpha below the threshold is a hole
+    Mask { threshold: f64 },
+    // alpha is the probability a ray hits, which averages out to partial coverage
+    Stochastic,
+}
+
+// wraps any material with an opacity texture, alpha is read from the texture's luminance
+pub struct Cutout {
+    pub material: Box<dyn Material>,
+    pub alpha: Arc<dyn Texture>,
+    pub mode: AlphaMode,
+}
+
+impl Cutout {
+    pub fn new(material: Box<dyn Material>, alpha: Arc<dyn Texture>, threshold: f64) -> Self {
+        Self {
+            material,
+            alpha,
+            mode: AlphaMode::Mask { threshold },
+        }
+    }
+
+    pub fn stochastic(material: Box<dyn Material>, alpha: Arc<dyn Texture>) -> Self {
+        Self {
+            material,
+            alpha,
+            mode: AlphaMode::Stochastic,
+        }
+    }
+}
+
+impl Material for Cutout {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &mut Ray) -> bool {
+        self.material.scatter(ray_in, hit_record, scattered)
+    }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Color {
+        self.material.eval(ray_in, hit_record, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        self.material.pdf(ray_in, hit_record, direction)
+    }
+
+    fn attenuation(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        self.material.attenuation(ray_in, hit_record, scattered)
+    }
+
+    fn clone_box(&self) -> Box<dyn Material> {
+        let material = Cutout {
+            material: self.material.clone(),
+            alpha: self.alpha.clone(),
+            mode: self.mode,
+        };
+        Box::new(material)
+    }
+
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        self.material.shading_normal(hit_record)
+    }
+
+    fn alpha_test(&self, hit_record: &HitRecord) -> bool {
+        let alpha = self
+            .alpha
+            .value(hit_record.u, hit_record.v, hit_record.point)
+            .luminance();
+
+        let opaque = match self.mode {
+            AlphaMode::Mask { threshold } => alpha >= threshold,
+            AlphaMode::Stochastic => alpha >= 1.0 || (alpha > 0.0 && random_float() < alpha),
+        };
+        opaque && self.material.alpha_test(hit_record)
+    }
+
+    fn interior(&self) -> Option<Medium> {
+        self.material.interior()
+    }
+}
+
+// random walk subsurface scattering, a smooth dielectric boundary around a
+// scattering medium that the integrator walks through until the path leaves
+pub struct Subsurface {
+    pub boundary: Dielectric,
+    pub medium: Medium,
+}
+
+impl Subsurface {
+    // albedo is the single scattering albedo, mean_free_path is in scene units
+    pub fn new(albedo: Color, mean_free_path: Color, refraction_index: f64) -> Self {
+        Self {
+            boundary: Dielectric::new(refraction_index),
+            medium: Medium::from_albedo(albedo, mean_free_path, 0.0),
+        }
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f64) -> Self {
+        self.medium = Medium::new(self.medium.sigma_a, self.medium.sigma_s, anisotropy);
+        self
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &mut Ray) -> bool {
+        self.boundary.scatter(ray_in, hit_record, scattered)
+    }
+
+    fn attenuation(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Color {
+        self.boundary.attenuation(ray_in, hit_record, scattered)
+    }
+
+    fn clone_box(&self) -> Box<dyn Material> {
+        let material = Subsurface {
+            boundary: self.boundary,
+            medium: self.medium,
+        };
+        Box::new(material)
+    }
+
+    fn interior(&self) -> Option<Medium> {
+        Some(self.medium)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::{
+        hittable::Hittable, interval::Interval, seed_random, shapes::Sphere, texture::SolidColor,
+    };
+
+    const SAMPLES: usize = 100_000;
+
+    fn hit_record(material: &dyn Material, front_face: bool) -> HitRecord {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        HitRecord {
+            normal,
+            geometric_normal: normal,
+            tangent: Vec3::new(1.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 1.0, 0.0),
+            material: material.clone_box(),
+            front_face,
+            ..HitRecord::default()
+        }
+    }
+
+    // arriving at the origin from cos_theta above the surface
+    fn ray_towards(cos_theta: f64) -> Ray {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        Ray {
+            origin: Vec3::new(sin_theta, 0.3 * sin_theta, cos_theta),
+            direction: Vec3::new(-sin_theta, -0.3 * sin_theta, -cos_theta),
+            wavelengths: SampledWavelengths::sample_visible(0.5),
+        }
+    }
+
+    // what a white furnace sends back after one bounce, from the material's own sampling.
+    // transmission is scaled back up by eta^2 when eta is given so it counts as energy
+    fn sampled_albedo(
+        material: &dyn Material,
+        hit_record: &HitRecord,
+        ray_in: &Ray,
+        eta: Option<f64>,
+    ) -> f64 {
+        let mut total = 0.0;
+        for _ in 0..SAMPLES {
+            let mut scattered = ray_towards(1.0);
+            if !material.scatter(ray_in, hit_record, &mut scattered) {
+                continue;
+            }
+
+            let attenuation = material.attenuation(ray_in, hit_record, &scattered);
+            let transmitted = dot(scattered.direction, hit_record.normal) < 0.0;
+            let scale = eta.filter(|_| transmitted).map_or(1.0, |eta| eta * eta);
+            total += attenuation.luminance() * scale;
+        }
+        total / SAMPLES as f64
+    }
+
+    // the same integral from eval over uniformly sampled directions, which only agrees
+    // when scatter, attenuation, eval and pdf all describe the same bsdf
+    fn integrated_albedo(material: &dyn Material, hit_record: &HitRecord, ray_in: &Ray) -> f64 {
+        let mut total = 0.0;
+        for _ in 0..SAMPLES {
+            let direction = Vec3::random_unit_vector();
+            total += material.eval(ray_in, hit_record, direction).luminance() * 4.0 * PI;
+        }
+        total / SAMPLES as f64
+    }
+
+    fn assert_close(a: f64, b: f64, tolerance: f64) {
+        assert!(
+            (a - b).abs() <= tolerance,
+            "{a} and {b} differ by more than {tolerance}"
+        );
+    }
+
+    #[test]
+    fn conductor_sampling_matches_eval() {
+        seed_random(1);
+        for anisotropy in [0.0, 0.6] {
+            let material = Conductor::gold(0.6);
+            let material = Conductor {
+                distribution: Ggx::from_roughness(0.6, anisotropy),
+                ..material
+            };
+            let hit_record = hit_record(&material, true);
+
+            for cos_theta in [0.9, 0.4] {
+                let ray_in = ray_towards(cos_theta);
+                let sampled = sampled_albedo(&material, &hit_record, &ray_in, None);
+                let integrated = integrated_albedo(&material, &hit_record, &ray_in);
+                assert_close(sampled, integrated, 0.02);
+            }
+        }
+    }
+
+    #[test]
+    fn conductor_white_furnace() {
+        seed_random(2);
+        // huge extinction reflects everything, so only masking loses energy
+        let mirror = |roughness| {
+            Conductor::new(
+                Color::new(1.0, 1.0, 1.0),
+                Color::new(1e4, 1e4, 1e4),
+                roughness,
+                0.0,
+            )
+        };
+
+        let smooth = mirror(0.0);
+        let ray_in = ray_towards(0.7);
+        let albedo = sampled_albedo(&smooth, &hit_record(&smooth, true), &ray_in, None);
+        assert_close(albedo, 1.0, 1e-3);
+
+        let rough = mirror(0.3);
+        let albedo = sampled_albedo(&rough, &hit_record(&rough, true), &ray_in, None);
+        assert!(albedo <= 1.0 + 1e-3 && albedo > 0.95, "albedo {albedo}");
+    }
+
+    #[test]
+    fn rough_dielectric_sampling_matches_eval() {
+        seed_random(3);
+        let material = RoughDielectric::new(1.5, 0.6);
+        for front_face in [true, false] {
+            let hit_record = hit_record(&material, front_face);
+            for cos_theta in [0.9, 0.4] {
+                let ray_in = ray_towards(cos_theta);
+                let sampled = sampled_albedo(&material, &hit_record, &ray_in, None);
+                let integrated = integrated_albedo(&material, &hit_record, &ray_in);
+                // uniform directions find the refracted lobe poorly, so it's noisier
+                assert_close(sampled, integrated, 0.04);
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_white_furnace() {
+        seed_random(4);
+        let material = RoughDielectric::new(1.5, 0.05);
+        for (front_face, eta) in [(true, 1.5), (false, 1.0 / 1.5)] {
+            let hit_record = hit_record(&material, front_face);
+            for cos_theta in [0.9, 0.4] {
+                let ray_in = ray_towards(cos_theta);
+                let albedo = sampled_albedo(&material, &hit_record, &ray_in, Some(eta));
+                assert!(albedo <= 1.0 + 1e-2 && albedo > 0.97, "albedo {albedo}");
+            }
+        }
+    }
+
+    #[test]
+    fn principled_sampling_matches_eval() {
+        seed_random(5);
+        let layered = Principled {
+            metallic: 0.4,
+            anisotropy: 0.5,
+            sheen: 1.0,
+            clearcoat: 1.0,
+            clearcoat_roughness: 0.3,
+            ..Principled::new(Color::new(0.9, 0.5, 0.2), 0.0, 0.5)
+        };
+        let glass = Principled {
+            transmission: 1.0,
+            ..Principled::new(Color::new(1.0, 1.0, 1.0), 0.0, 0.6)
+        };
+
+        for material in [Principled::default(), layered, glass] {
+            let hit_record = hit_record(&material, true);
+            for cos_theta in [0.9, 0.4] {
+                let ray_in = ray_towards(cos_theta);
+                let sampled = sampled_albedo(&material, &hit_record, &ray_in, None);
+                let integrated = integrated_albedo(&material, &hit_record, &ray_in);
+                assert_close(sampled, integrated, 0.04);
+            }
+        }
+    }
+
+    #[test]
+    fn principled_white_furnace() {
+        seed_random(6);
+        let metal = Principled::new(Color::new(1.0, 1.0, 1.0), 1.0, 0.3);
+        let glass = Principled {
+            transmission: 1.0,
+            ..Principled::new(Color::new(1.0, 1.0, 1.0), 0.0, 0.05)
+        };
+        let brushed_glass = Principled {
+            transmission: 1.0,
+            anisotropy: 0.8,
+            ..Principled::new(Color::new(1.0, 1.0, 1.0), 0.0, 0.1)
+        };
+
+        for (material, eta) in [
+            (metal, None),
+            (glass, Some(glass.refraction_index)),
+            (brushed_glass, Some(brushed_glass.refraction_index)),
+        ] {
+            let hit_record = hit_record(&material, true);
+            for cos_theta in [0.9, 0.4] {
+                let ray_in = ray_towards(cos_theta);
+                let albedo = sampled_albedo(&material, &hit_record, &ray_in, eta);
+                assert!(albedo <= 1.0 + 1e-2 && albedo > 0.95, "albedo {albedo}");
+            }
+        }
+    }
+
+    // mean spread of the refracted rays along x and y, seen straight on
+    fn transmitted_spread(material: &dyn Material, tangent: Vec3) -> (f64, f64) {
+        let hit_record = HitRecord {
+            tangent,
+            bitangent: cross(Vec3::new(0.0, 0.0, 1.0), tangent),
+            ..hit_record(material, true)
+        };
+        let ray_in = ray_towards(1.0);
+
+        let (mut x, mut y) = (0.0, 0.0);
+        for _ in 0..20_000 {
+            let mut scattered = ray_towards(1.0);
+            if material.scatter(&ray_in, &hit_record, &mut scattered) && scattered.direction.z < 0.0
+            {
+                let direction = scattered.direction.unit_vector();
+                x += direction.x.abs();
+                y += direction.y.abs();
+            }
+        }
+        (x, y)
+    }
+
+    #[test]
+    fn principled_anisotropic_transmission_follows_the_tangent() {
+        seed_random(7);
+        let material = Principled {
+            transmission: 1.0,
+            anisotropy: 0.8,
+            ..Principled::new(Color::new(1.0, 1.0, 1.0), 0.0, 0.5)
+        };
+
+        let (x, y) = transmitted_spread(&material, Vec3::new(1.0, 0.0, 0.0));
+        let (rotated_x, rotated_y) = transmitted_spread(&material, Vec3::new(0.0, 1.0, 0.0));
+        assert!(x / y > 1.5, "spread {x} by {y}");
+        assert!(
+            rotated_y / rotated_x > 1.5,
+            "spread {rotated_x} by {rotated_y}"
+        );
+    }
+
+    // leaving the glass after a path of length d inside keeps exp(-sigma d) of the light,
+    // however long the ray's direction vector happens to be
+    #[test]
+    fn tinted_glass_follows_beer_lambert() {
+        let material = Dielectric::tinted(1.5, Color::new(0.5, 0.8, 0.1), 2.0);
+        let sigma = material.absorption;
+        assert!((sigma.r - 0.5f64.ln() / -2.0).abs() < 1e-12);
+
+        let scattered = ray_towards(1.0);
+        for (distance, length) in [(0.0, 1.0), (0.5, 1.0), (2.0, 1.0), (3.0, 2.5)] {
+            let ray_in = Ray {
+                direction: Vec3::new(0.0, 0.0, -length),
+                ..ray_towards(1.0)
+            };
+            let leaving = HitRecord {
+                t: distance / length,
+                ..hit_record(&material, false)
+            };
+
+            let transmittance = material.attenuation(&ray_in, &leaving, &scattered);
+            for (got, sigma) in [
+                (transmittance.r, sigma.r),
+                (transmittance.g, sigma.g),
+                (transmittance.b, sigma.b),
+            ] {
+                assert!((got - (-sigma * distance).exp()).abs() < 1e-12);
+            }
+        }
+
+        // the tint is exactly the colour asked for at the distance it was given for
+        let leaving = HitRecord {
+            t: 2.0,
+            ..hit_record(&material, false)
+        };
+        let tint = material.attenuation(&ray_towards(1.0), &leaving, &scattered);
+        assert!((tint.r - 0.5).abs() < 1e-12 && (tint.b - 0.1).abs() < 1e-12);
+
+        // nothing is absorbed on the way in
+        let entering = HitRecord {
+            t: 2.0,
+            ..hit_record(&material, true)
+        };
+        let clear = material.attenuation(&ray_towards(1.0), &entering, &scattered);
+        assert_eq!((clear.r, clear.g, clear.b), (1.0, 1.0, 1.0));
+    }
+
+    // how many of a batch of rays at the middle of a unit sphere wrapped in the cutout hit it
+    fn cutout_hits(cutout: Cutout) -> usize {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Box::new(cutout));
+        (0..10_000)
+            .filter(|_| {
+                let ray = Ray {
+                    origin: Vec3::new(0.0, 0.0, 3.0),
+                    direction: Vec3::new(0.0, 0.0, -1.0),
+                    wavelengths: SampledWavelengths::sample_visible(0.5),
+                };
+                let interval = Interval {
+                    min: 1e-3,
+                    max: f64::INFINITY,
+                };
+                sphere.hit(&ray, interval, &mut HitRecord::default())
+            })
+            .count()
+    }
+
+    #[test]
+    fn cutout_alpha_decides_hits() {
+        seed_random(8);
+        let lambertian = || Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let alpha = |a: f64| Arc::new(SolidColor::new(Color::new(a, a, a)));
+
+        for threshold in [0.0001, 0.5, 1.0] {
+            assert_eq!(
+                cutout_hits(Cutout::new(lambertian(), alpha(0.0), threshold)),
+                0
+            );
+            assert_eq!(
+                cutout_hits(Cutout::new(lambertian(), alpha(1.0), threshold)),
+                10_000
+            );
+        }
+        assert_eq!(cutout_hits(Cutout::stochastic(lambertian(), alpha(0.0))), 0);
+        assert_eq!(
+            cutout_hits(Cutout::stochastic(lambertian(), alpha(1.0))),
+            10_000
+        );
+
+        // partial alpha is a coin toss at each of the sphere's two surfaces
+        let hits = cutout_hits(Cutout::stochastic(lambertian(), alpha(0.3)));
+        assert!((hits as f64 / 10_000.0 - 0.51).abs() < 0.02, "{hits}");
+    }
 }
@@ -0,0 +1,163 @@
+use super::{color::Color, onb::Onb, random_float, vec3::Vec3, PI};
+
+// homogeneous participating medium, coefficients are per channel in inverse scene units
+#[derive(Clone, Copy)]
+pub struct Medium {
+    pub sigma_a: Color,
+    pub sigma_s: Color,
+    // henyey-greenstein asymmetry, 0 scatters isotropically and positive values forwards
+    pub anisotropy: f64,
+}
+
+// a path through a medium, distances are sampled with one channel for the whole
+// walk and weighted against the pdfs every channel would have had for the same path.
+// picking the channel per step instead leaves the dense channels with huge variance
+#[derive(Clone, Copy)]
+pub struct Walk {
+    pub medium: Medium,
+    channel: usize,
+    // path pdf of each channel relative to the sampled one
+    pdf_ratios: Color,
+}
+
+pub struct DistanceSample {
+    pub distance: f64,
+    // false when the sample ran past the end of the segment
+    pub scattered: bool,
+    // transmittance (times sigma_s for a scatter) over the pdf of the sample, mis'd over the channels
+    pub weight: Color,
+}
+
+impl Medium {
+    pub fn new(sigma_a: Color, sigma_s: Color, anisotropy: f64) -> Self {
+        Self {
+            sigma_a,
+            sigma_s,
+            anisotropy: anisotropy.clamp(-0.99, 0.99),
+        }
+    }
+
+    // the usual artist facing parameterization, single scattering albedo and the
+    // mean distance travelled between interactions
+    pub fn from_albedo(albedo: Color, mean_free_path: Color, anisotropy: f64) -> Self {
+        let coefficient = |mfp: f64| 1.0 / mfp.max(1e-6);
+        let sigma_t = Color::new(
+            coefficient(mean_free_path.r),
+            coefficient(mean_free_path.g),
+            coefficient(mean_free_path.b),
+        );
+        let albedo = Color::new(
+            albedo.r.clamp(0.0, 1.0),
+            albedo.g.clamp(0.0, 1.0),
+            albedo.b.clamp(0.0, 1.0),
+        );
+        let sigma_s = sigma_t * albedo;
+        let sigma_a = Color::new(
+            sigma_t.r - sigma_s.r,
+            sigma_t.g - sigma_s.g,
+            sigma_t.b - sigma_s.b,
+        );
+
+        Self::new(sigma_a, sigma_s, anisotropy)
+    }
+
+    pub fn sigma_t(&self) -> Color {
+        self.sigma_a + self.sigma_s
+    }
+
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let sigma_t = self.sigma_t();
+        Color::new(
+            (-sigma_t.r * distance).exp(),
+            (-sigma_t.g * distance).exp(),
+            (-sigma_t.b * distance).exp(),
+        )
+    }
+
+    // samples the henyey-greenstein phase function around the direction of travel,
+    // it's its own pdf so there is nothing left to weight
+    pub fn sample_phase(&self, direction: Vec3) -> Vec3 {
+        let g = self.anisotropy;
+        let u = random_float();
+
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 + g - 2.0 * g * u);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_float();
+
+        Onb::new(direction).transform(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+impl Walk {
+    pub fn new(medium: Medium) -> Self {
+        Self {
+            medium,
+            channel: ((random_float() * 3.0) as usize).min(2),
+            pdf_ratios: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // samples where along a segment of max_distance the next interaction is, the
+    // returned walk carries on from there
+    pub fn sample_distance(&self, max_distance: f64) -> (DistanceSample, Walk) {
+        let sigma_t = self.medium.sigma_t();
+        let sigma = channel(sigma_t, self.channel);
+
+        let distance = if sigma > 0.0 {
+            -(1.0 - random_float()).ln() / sigma
+        } else {
+            f64::INFINITY
+        };
+        let scattered = distance < max_distance;
+        let distance = distance.min(max_distance);
+
+        let transmittance = self.medium.transmittance(distance);
+        let (density, contribution) = if scattered {
+            (sigma_t * transmittance, transmittance * self.medium.sigma_s)
+        } else {
+            (transmittance, transmittance)
+        };
+
+        let pdf = channel(density, self.channel);
+        if pdf == 0.0 {
+            let sample = DistanceSample {
+                distance,
+                scattered,
+                weight: Color::new(0.0, 0.0, 0.0),
+            };
+            return (sample, *self);
+        }
+
+        let pdf_ratios = self.pdf_ratios * density * (1.0 / pdf);
+        let mean = |c: Color| (c.r + c.g + c.b) / 3.0;
+        let weight = contribution * (mean(self.pdf_ratios) / (pdf * mean(pdf_ratios)));
+
+        let walk = Walk {
+            pdf_ratios,
+            ..*self
+        };
+        let sample = DistanceSample {
+            distance,
+            scattered,
+            weight,
+        };
+        (sample, walk)
+    }
+}
+
+fn channel(color: Color, index: usize) -> f64 {
+    match index {
+        0 => color.r,
+        1 => color.g,
+        _ => color.b,
+    }
+}
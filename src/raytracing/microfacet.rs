@@ -0,0 +1,119 @@
+use super::{
+    vec3::{cross, dot, Vec3},
+    PI,
+};
+
+// anisotropic GGX / Trowbridge-Reitz distribution, all vectors are in the local
+// shading frame with the normal along +z
+
+// below this the lobe is treated as a perfect mirror
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+#[derive(Clone, Copy)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self { alpha_x, alpha_y }
+    }
+
+    // perceptual roughness and anisotropy in [0, 1], as in the disney brdf
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        Self::new(alpha / aspect, alpha * aspect)
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let cos2 = wm.z * wm.z;
+        if cos2 == 0.0 {
+            return 0.0;
+        }
+
+        let e = ((wm.x * wm.x) / (self.alpha_x * self.alpha_x)
+            + (wm.y * wm.y) / (self.alpha_y * self.alpha_y))
+            / cos2;
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos2 * cos2 * (1.0 + e) * (1.0 + e))
+    }
+
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+
+        let alpha2_tan2 = (w.x * w.x * self.alpha_x * self.alpha_x
+            + w.y * w.y * self.alpha_y * self.alpha_y)
+            / (w.z * w.z);
+        ((1.0 + alpha2_tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // density of visible normals as seen from wo
+    pub fn visible_d(&self, wo: Vec3, wm: Vec3) -> f64 {
+        let cos_o = wo.z.abs();
+        if cos_o == 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) / cos_o * self.d(wm) * (wo.x * wm.x + wo.y * wm.y + wo.z * wm.z).abs()
+    }
+
+    // Heitz, "Sampling the GGX Distribution of Visible Normals"
+    // https://jcgt.org/published/0007/04/01/
+    pub fn sample_visible_normal(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        let flip = wo.z < 0.0;
+        let wo = if flip { wo * -1.0 } else { wo };
+
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).unit_vector();
+
+        let lensq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if lensq > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / lensq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(vh, t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+
+        let nh = (t1 * p1) + (t2 * p2) + (vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt());
+        let wm = Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).unit_vector();
+
+        if flip {
+            wm * -1.0
+        } else {
+            wm
+        }
+    }
+}
+
+// refracts wo (pointing away from the surface) through a facet with normal n on
+// the same side, eta is transmitted over incident index. None on total internal reflection
+pub fn refract(wo: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = dot(wo, n);
+    let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some((wo * -1.0) / eta + n * (cos_theta_i / eta - cos_theta_t))
+}
@@ -1,23 +1,60 @@
+pub mod adaptive;
+pub mod aperture;
+pub mod background;
 pub mod camera;
 pub mod color;
+pub mod distribution;
+pub mod film;
+pub mod fresnel;
+pub mod hdr;
 pub mod hittable;
+pub mod image;
 pub mod interval;
+pub mod lens;
+pub mod lights;
 pub mod materials;
+pub mod medium;
+pub mod microfacet;
+pub mod onb;
 pub mod point;
 pub mod ray;
+pub mod sampler;
 pub mod shapes;
+pub mod sky;
+pub mod spectrum;
+pub mod stereo;
+pub mod texture;
 pub mod vec3;
 
-const PI: f64 = 3.1415926535897932385;
+use std::cell::RefCell;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+pub const PI: f64 = std::f64::consts::PI;
 
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * (PI / 180.0)
 }
 
+thread_local! {
+    // only used outside of rendering, while the camera renders it installs a sampler
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_os_rng());
+}
+
+// reseeds the fallback generator on this thread, so a scene built from random_float comes out the same
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
 pub fn random_float() -> f64 {
-    rand::random_range(0.0..1.0)
+    sampler::next_1d().unwrap_or_else(unsampled_float)
+}
+
+// the plain generator, for when no sample has been started
+fn unsampled_float() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().random_range(0.0..1.0))
 }
 
 pub fn random_float_range(min: f64, max: f64) -> f64 {
-    rand::random_range(min..max)
+    min + (max - min) * random_float()
 }
@@ -0,0 +1,49 @@
+use super::vec3::{cross, dot, Vec3};
+
+// orthonormal basis around w, from the rest of your life book
+#[derive(Clone, Copy)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = cross(w, a).unit_vector();
+        let u = cross(w, v);
+
+        Self { u, v, w }
+    }
+
+    // u follows the tangent projected onto the plane of n, so anisotropic lobes
+    // line up with the surface parameterization. falls back to new when the tangent
+    // is missing or parallel to n
+    pub fn from_tangent(n: Vec3, tangent: Vec3) -> Self {
+        let w = n.unit_vector();
+        let projected = tangent - (w * dot(tangent, w));
+        if projected.length_squared() < 1e-12 {
+            return Self::new(n);
+        }
+
+        let u = projected.unit_vector();
+        let v = cross(w, u);
+        Self { u, v, w }
+    }
+
+    // local coordinates to world
+    pub fn transform(&self, local: Vec3) -> Vec3 {
+        (self.u * local.x) + (self.v * local.y) + (self.w * local.z)
+    }
+
+    // world coordinates to local
+    pub fn to_local(&self, world: Vec3) -> Vec3 {
+        Vec3::new(dot(world, self.u), dot(world, self.v), dot(world, self.w))
+    }
+}
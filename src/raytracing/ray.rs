@@ -1,14 +1,31 @@
-use crate::raytracing::{color::Color, vec3::Vec3};
+use crate::raytracing::{
+    color::Color,
+    vec3::{dot, Vec3},
+};
 
 use super::{
+    background::Background,
     hittable::{HitRecord, HittableList},
     interval::Interval,
+    medium::Walk,
+    random_float,
+    sampler::{self, Slot},
+    spectrum::{Illuminant, SampledWavelengths},
 };
 
+// scattering steps a random walk can take before russian roulette starts, and
+// before it's given up on altogether
+const ROULETTE_STEPS: u32 = 16;
+const MAX_WALK_STEPS: u32 = 65536;
+
+#[cfg(feature = "spectral")]
+use super::spectrum::SampledSpectrum;
+
 #[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    pub wavelengths: SampledWavelengths,
 }
 
 impl Ray {
@@ -24,6 +41,7 @@ impl Ray {
                 y: 0.0,
                 z: 0.0,
             },
+            wavelengths: SampledWavelengths::default(),
         }
     }
 
@@ -31,69 +49,498 @@ impl Ray {
         self.origin + (self.direction * f)
     }
 
-    pub fn color(&self, world: &HittableList, depth: u32) -> Color {
+    pub fn color(&self, world: &HittableList, background: &dyn Background, depth: u32) -> Color {
+        to_rgb(
+            self.trace(world, background, depth, None, None),
+            &self.wavelengths,
+        )
+    }
+
+    // bsdf_pdf is the pdf of the bounce that produced this ray, or None if it
+    // came from the camera or a specular bounce and so can't be mis weighted.
+    // walk is the medium the ray is travelling through, None for empty space
+    fn trace(
+        &self,
+        world: &HittableList,
+        background: &dyn Background,
+        depth: u32,
+        bsdf_pdf: Option<f64>,
+        walk: Option<Walk>,
+    ) -> Radiance {
         if depth == 0 {
-            return Color {
-                r: 0.0,
-                g: 0.0,
-                b: 0.0,
-            };
+            return black();
         }
 
+        // the random walk scatters through the medium until it reaches a surface.
+        // its steps have their own budget instead of using up bounces, so dense media
+        // aren't cut short, and russian roulette ends the long ones
+        let mut ray = *self;
+        let mut bsdf_pdf = bsdf_pdf;
+        let mut walk = walk;
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut steps = 0;
         let mut hit_record: HitRecord = HitRecord::default();
 
-        if world.hit(
-            self,
-            Interval {
-                min: 0.001,
-                max: f64::INFINITY,
-            },
-            &mut hit_record,
-        ) {
-            let mut scattered: Ray = Ray::default();
+        loop {
+            sampler::start_vertex();
+            sampler::start_slot(Slot::Intersection);
+            if !world.hit(
+                &ray,
+                Interval {
+                    min: 0.001,
+                    max: f64::INFINITY,
+                },
+                &mut hit_record,
+            ) {
+                return ray.escape(background, bsdf_pdf)
+                    * reflectance(throughput, &self.wavelengths);
+            }
 
-            if hit_record
-                .material
-                .scatter(self, &hit_record, &mut scattered)
-            {
-                let attenuation = hit_record.material.attenuation();
-                return scattered.color(world, depth - 1) * attenuation;
-            } else {
-                return Color {
-                    r: 0.0,
-                    g: 0.0,
-                    b: 0.0,
-                };
+            let Some(current) = walk else {
+                break;
+            };
+            let length = ray.direction.length();
+            sampler::start_slot(Slot::Distance);
+            let (sample, next) = current.sample_distance(hit_record.t * length);
+            throughput *= sample.weight;
+            walk = Some(next);
+            if !sample.scattered {
+                break;
+            }
+
+            steps += 1;
+            if steps > MAX_WALK_STEPS {
+                return black();
+            }
+            if steps > ROULETTE_STEPS {
+                let survival = throughput.r.max(throughput.g).max(throughput.b).min(1.0);
+                sampler::start_slot(Slot::Roulette);
+                if random_float() >= survival {
+                    return black();
+                }
+                throughput *= 1.0 / survival;
             }
-            // let direction = hit_record.normal + Vec3::random_unit_vector();
-            // let new_ray = Ray {
-            //     origin: hit_record.point,
-            //     direction,
-            // };
 
-            // return new_ray.color(world, depth - 1) * 0.7;
+            sampler::start_slot(Slot::Phase);
+            ray = Ray {
+                origin: ray.at(sample.distance / length),
+                direction: current.medium.sample_phase(ray.direction.unit_vector()),
+                wavelengths: ray.wavelengths,
+            };
+            // the phase function is sampled exactly, there is nothing to mis against
+            bsdf_pdf = None;
         }
 
-        let unit_direction = self.direction.unit_vector();
-        let a = 0.5 * (unit_direction.y + 1.0);
+        ray.shade(world, background, depth, walk, hit_record)
+            * reflectance(throughput, &self.wavelengths)
+    }
+
+    // the light leaving a surface hit back along this ray
+    fn shade(
+        &self,
+        world: &HittableList,
+        background: &dyn Background,
+        depth: u32,
+        walk: Option<Walk>,
+        mut hit_record: HitRecord,
+    ) -> Radiance {
+        hit_record.normal = hit_record.material.shading_normal(&hit_record);
+
+        let direct = self.sample_background(world, background, &hit_record)
+            + self.sample_lights(world, &hit_record);
 
-        let full = Vec3 {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
+        let mut scattered: Ray = Ray {
+            wavelengths: self.wavelengths,
+            ..Ray::default()
         };
-        let shaded = Vec3 {
-            x: 0.5,
-            y: 0.7,
-            z: 1.0,
+
+        sampler::start_slot(Slot::Scatter);
+        if !(hit_record
+            .material
+            .scatter(self, &hit_record, &mut scattered)
+            && consistent_side(&hit_record, scattered.direction))
+        {
+            return direct;
+        }
+
+        let mut attenuation = reflectance(
+            hit_record
+                .material
+                .attenuation(self, &hit_record, &scattered),
+            &self.wavelengths,
+        ) * terminator_shadowing(&hit_record, scattered.direction);
+
+        // a dispersive bounce collapses the path to the primary wavelength, from
+        // here on it carries that wavelength's share of the result
+        if scattered.wavelengths.secondary_terminated() && !self.wavelengths.secondary_terminated()
+        {
+            attenuation *= dispersion_weight(&scattered.wavelengths);
+        }
+        let pdf = hit_record
+            .material
+            .pdf(self, &hit_record, scattered.direction);
+        let next_pdf = if pdf > 0.0 { Some(pdf) } else { None };
+
+        // transmission enters the surface's interior through a front face and
+        // leaves it through a back face, reflection stays where it was
+        let next_walk = if dot(scattered.direction, hit_record.geometric_normal) < 0.0 {
+            if hit_record.front_face {
+                sampler::start_slot(Slot::Interior);
+                hit_record.material.interior().map(Walk::new)
+            } else {
+                None
+            }
+        } else {
+            walk
         };
 
-        let combined = (full * (1.0 - a)) + (shaded * a);
+        direct + scattered.trace(world, background, depth - 1, next_pdf, next_walk) * attenuation
+    }
+
+    // the background seen by a ray that left the scene
+    fn escape(&self, background: &dyn Background, bsdf_pdf: Option<f64>) -> Radiance {
+        let emitted = emission(
+            background.color(self.direction),
+            &Illuminant::d65(),
+            &self.wavelengths,
+        );
+        match bsdf_pdf {
+            Some(pdf) => emitted * power_heuristic(pdf, background.pdf(self.direction)),
+            None => emitted,
+        }
+    }
+
+    // next event estimation towards the background, mis weighted against bsdf sampling
+    fn sample_background(
+        &self,
+        world: &HittableList,
+        background: &dyn Background,
+        hit_record: &HitRecord,
+    ) -> Radiance {
+        sampler::start_slot(Slot::Background);
+        let Some(sample) = background.sample() else {
+            return black();
+        };
+
+        if !consistent_side(hit_record, sample.direction) {
+            return black();
+        }
+        let f = hit_record.material.eval(self, hit_record, sample.direction)
+            * terminator_shadowing(hit_record, sample.direction);
+        if f.near_zero() || sample.radiance.near_zero() {
+            return black();
+        }
+
+        if occluded(world, hit_record.point, sample.direction, f64::INFINITY) {
+            return black();
+        }
+
+        let bsdf_pdf = hit_record.material.pdf(self, hit_record, sample.direction);
+        let weight = power_heuristic(sample.pdf, bsdf_pdf);
+
+        reflectance(f, &self.wavelengths)
+            * emission(sample.radiance, &Illuminant::d65(), &self.wavelengths)
+            * (weight / sample.pdf)
+    }
+
+    // delta lights can only be reached by shadow rays, so there is nothing to mis against
+    fn sample_lights(&self, world: &HittableList, hit_record: &HitRecord) -> Radiance {
+        let mut direct = black();
+
+        for light in &world.lights {
+            let Some(sample) = light.sample(hit_record.point) else {
+                continue;
+            };
+
+            if !consistent_side(hit_record, sample.direction) {
+                continue;
+            }
+            let f = hit_record.material.eval(self, hit_record, sample.direction)
+                * terminator_shadowing(hit_record, sample.direction);
+            if f.near_zero() || sample.radiance.near_zero() {
+                continue;
+            }
+
+            if occluded(world, hit_record.point, sample.direction, sample.distance) {
+                continue;
+            }
+
+            direct += reflectance(f, &self.wavelengths)
+                * emission(sample.radiance, &sample.illuminant, &self.wavelengths);
+        }
+
+        direct
+    }
+}
+
+// the integrator carries rgb by default, or sampled spectra with the spectral feature
+#[cfg(not(feature = "spectral"))]
+type Radiance = Color;
+
+#[cfg(feature = "spectral")]
+type Radiance = SampledSpectrum;
+
+#[cfg(not(feature = "spectral"))]
+fn black() -> Radiance {
+    Color::new(0.0, 0.0, 0.0)
+}
+
+#[cfg(feature = "spectral")]
+fn black() -> Radiance {
+    SampledSpectrum::splat(0.0)
+}
+
+#[cfg(not(feature = "spectral"))]
+fn reflectance(color: Color, _wavelengths: &SampledWavelengths) -> Radiance {
+    color
+}
+
+#[cfg(feature = "spectral")]
+fn reflectance(color: Color, wavelengths: &SampledWavelengths) -> Radiance {
+    SampledSpectrum::from_reflectance(color, wavelengths)
+}
+
+#[cfg(not(feature = "spectral"))]
+fn emission(color: Color, illuminant: &Illuminant, _wavelengths: &SampledWavelengths) -> Radiance {
+    color * illuminant.rgb()
+}
+
+#[cfg(feature = "spectral")]
+fn emission(color: Color, illuminant: &Illuminant, wavelengths: &SampledWavelengths) -> Radiance {
+    SampledSpectrum::from_illuminant(color, illuminant, wavelengths)
+}
+
+#[cfg(not(feature = "spectral"))]
+fn dispersion_weight(wavelengths: &SampledWavelengths) -> Radiance {
+    wavelengths.rgb_weight()
+}
+
+#[cfg(feature = "spectral")]
+fn dispersion_weight(_wavelengths: &SampledWavelengths) -> Radiance {
+    SampledSpectrum::primary_mask()
+}
+
+#[cfg(not(feature = "spectral"))]
+fn to_rgb(radiance: Radiance, _wavelengths: &SampledWavelengths) -> Color {
+    radiance
+}
+
+#[cfg(feature = "spectral")]
+fn to_rgb(radiance: Radiance, wavelengths: &SampledWavelengths) -> Color {
+    radiance.to_rgb(wavelengths)
+}
+
+// a perturbed shading normal can claim a direction is above the surface when the
+// real surface says it's below, following those directions leaks light
+fn consistent_side(hit_record: &HitRecord, direction: Vec3) -> bool {
+    dot(direction, hit_record.normal) * dot(direction, hit_record.geometric_normal) > 0.0
+}
+
+// Chiang et al. "Taming the Shadow Terminator", softens the hard cutoff bumped
+// normals get where the geometric surface turns away from the light
+fn terminator_shadowing(hit_record: &HitRecord, direction: Vec3) -> f64 {
+    let direction = direction.unit_vector();
+    let cos_shading = dot(direction, hit_record.normal);
+    let cos_geometric = dot(direction, hit_record.geometric_normal);
+    let cos_normals = dot(hit_record.normal, hit_record.geometric_normal);
+    if cos_shading <= 0.0 || cos_normals <= 0.0 {
+        return 1.0;
+    }
+
+    let g = (cos_geometric / (cos_shading * cos_normals)).min(1.0);
+    -g * g * g + g * g + g
+}
+
+fn occluded(world: &HittableList, origin: Vec3, direction: Vec3, distance: f64) -> bool {
+    let shadow_ray = Ray {
+        origin,
+        direction,
+        wavelengths: SampledWavelengths::default(),
+    };
+    let mut shadow_record = HitRecord::default();
+
+    world.hit(
+        &shadow_ray,
+        Interval {
+            min: 0.001,
+            max: distance - 0.001,
+        },
+        &mut shadow_record,
+    )
+}
+
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
 
-        Color {
-            r: combined.x,
-            g: combined.y,
-            b: combined.z,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::{
+        background::Gradient,
+        fresnel::ThinFilm,
+        lights::PointLight,
+        materials::{Dielectric, Dispersion, Lambertian, Subsurface},
+        seed_random,
+        shapes::Sphere,
+        vec3::Point,
+        PI,
+    };
+
+    // mean colour of rays fired at the middle of the scene
+    fn mean_color(world: &HittableList, background: &dyn Background) -> Color {
+        let samples = 50_000;
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        for i in 0..samples {
+            let offset = Vec3::new(random_float() - 0.5, random_float() - 0.5, 0.0);
+            let ray = Ray {
+                origin: Point::new(0.0, 0.0, 3.0) + offset,
+                direction: Vec3::new(0.0, 0.0, -1.0),
+                wavelengths: SampledWavelengths::sample_visible((i as f64 + 0.5) / samples as f64),
+            };
+            total += ray.color(world, background, 64);
+        }
+        total * (1.0 / samples as f64)
+    }
+
+    // clear glass in a uniform white environment neither adds nor loses light, so it
+    // has to vanish against the background whatever the wavelengths do inside it
+    #[test]
+    fn glass_white_furnace() {
+        seed_random(7);
+        let background = Gradient::new(Color::new(1.0, 1.0, 1.0), Color::new(1.0, 1.0, 1.0));
+        let empty = mean_color(&HittableList::default(), &background);
+
+        for material in [
+            Dielectric::new(1.5),
+            Dielectric::dispersive(Dispersion::BK7),
+            Dielectric::dispersive(Dispersion::DENSE_FLINT),
+        ] {
+            let mut world = HittableList::default();
+            world.add(Box::new(Sphere::new(
+                Point::new(0.0, 0.0, 0.0),
+                1.0,
+                Box::new(material),
+            )));
+
+            let color = mean_color(&world, &background);
+            for (glass, empty) in [(color.r, empty.r), (color.g, empty.g), (color.b, empty.b)] {
+                assert!(
+                    (glass / empty - 1.0).abs() < 0.02,
+                    "{glass} against {empty}"
+                );
+            }
+        }
+    }
+
+    // the film only moves energy between reflection and transmission, so a coated
+    // sphere has to vanish against the background like clear glass
+    #[test]
+    fn coated_glass_white_furnace() {
+        seed_random(8);
+        let background = Gradient::new(Color::new(1.0, 1.0, 1.0), Color::new(1.0, 1.0, 1.0));
+        let empty = mean_color(&HittableList::default(), &background);
+
+        for film in [ThinFilm::new(300.0, 1.33), ThinFilm::new(550.0, 2.0)] {
+            let mut world = HittableList::default();
+            world.add(Box::new(Sphere::new(
+                Point::new(0.0, 0.0, 0.0),
+                1.0,
+                Box::new(Dielectric::new(1.5).with_thin_film(film)),
+            )));
+
+            let color = mean_color(&world, &background);
+            for (coated, empty) in [(color.r, empty.r), (color.g, empty.g), (color.b, empty.b)] {
+                assert!(
+                    (coated / empty - 1.0).abs() < 0.02,
+                    "{coated} against {empty}"
+                );
+            }
+        }
+    }
+
+    // a point light of intensity i at distance r and angle theta off the normal lights a
+    // lambertian surface with i cos(theta) / r^2, which it reflects as albedo / pi of that
+    #[test]
+    fn point_light_falls_off_with_distance_squared() {
+        seed_random(9);
+        let black = Gradient::new(Color::new(0.0, 0.0, 0.0), Color::new(0.0, 0.0, 0.0));
+        // grey, so the spectral uplift reproduces it exactly
+        let albedo = 0.6;
+        let intensity = 3.0;
+        let height = 2.0;
+
+        let mut world = HittableList::default();
+        world.add(Box::new(Sphere::new(
+            Point::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Box::new(Lambertian::new(Color::new(albedo, albedo, albedo))),
+        )));
+        world.add_light(Box::new(PointLight::new(
+            Point::new(0.0, height, 0.0),
+            Color::new(intensity, intensity, intensity),
+        )));
+
+        for x in [0.0, 0.5, 1.5] {
+            let samples = 20_000;
+            let mut total = Color::new(0.0, 0.0, 0.0);
+            for i in 0..samples {
+                let ray = Ray {
+                    origin: Point::new(x, 1.0, 0.0),
+                    direction: Vec3::new(0.0, -1.0, 0.0),
+                    wavelengths: SampledWavelengths::sample_visible(
+                        (i as f64 + 0.5) / samples as f64,
+                    ),
+                };
+                total += ray.color(&world, &black, 4);
+            }
+            let color = total * (1.0 / samples as f64);
+
+            let distance_squared = x * x + height * height;
+            let cos_theta = height / distance_squared.sqrt();
+            let expected = intensity * cos_theta / distance_squared * albedo / PI;
+            for got in [color.r, color.g, color.b] {
+                assert!(
+                    (got / expected - 1.0).abs() < 0.02,
+                    "{got} against {expected} at x = {x}"
+                );
+            }
+        }
+    }
+
+    // with no absorption and no refraction at the boundary, every path that walks into
+    // the medium comes back out, so the sphere has to vanish in a white furnace
+    #[test]
+    fn random_walk_white_furnace() {
+        seed_random(10);
+        let background = Gradient::new(Color::new(1.0, 1.0, 1.0), Color::new(1.0, 1.0, 1.0));
+        let empty = mean_color(&HittableList::default(), &background);
+
+        for mean_free_path in [1.0, 0.2] {
+            let mut world = HittableList::default();
+            world.add(Box::new(Sphere::new(
+                Point::new(0.0, 0.0, 0.0),
+                1.0,
+                Box::new(Subsurface::new(
+                    Color::new(1.0, 1.0, 1.0),
+                    Color::new(mean_free_path, mean_free_path, mean_free_path),
+                    1.0,
+                )),
+            )));
+
+            let color = mean_color(&world, &background);
+            for (walked, empty) in [(color.r, empty.r), (color.g, empty.g), (color.b, empty.b)] {
+                assert!(
+                    (walked / empty - 1.0).abs() < 0.02,
+                    "{walked} against {empty} with a mean free path of {mean_free_path}"
+                );
+            }
         }
     }
 }
@@ -0,0 +1,570 @@
+use std::cell::RefCell;
+
+use super::unsampled_float;
+
+// samplers hand out the random numbers for one camera sample, indexed by dimension.
+// while one is installed random_float draws from it. the camera has the first
+// CAMERA_DIMENSIONS and every vertex of the path a block of VERTEX_DIMENSIONS after
+// that, split into slots for each kind of decision, so a bounce's bsdf sample lands
+// on the same dimensions in every sample of a pixel whatever ran before it
+
+#[derive(Clone, Copy)]
+pub struct SampleIndex {
+    pub x: u32,
+    pub y: u32,
+    // which sample of the pixel this is, out of count
+    pub index: u32,
+    pub count: u32,
+    pub seed: u64,
+}
+
+pub trait Sampler: Send + Sync {
+    fn start_sample(&mut self, sample: SampleIndex);
+    // one dimension of the current sample
+    fn get_1d(&mut self, dimension: u64) -> f64;
+    // saved with checkpoints, a resume has to carry on the same sequence
+    fn name(&self) -> &'static str;
+    // whether the values are laid out for SampleIndex::count, which then can't change
+    // partway through a render
+    fn uses_count(&self) -> bool {
+        false
+    }
+    fn clone_box(&self) -> Box<dyn Sampler>;
+}
+
+// pixel offset, lens and wavelength
+const CAMERA_DIMENSIONS: u64 = 8;
+const VERTEX_DIMENSIONS: u64 = 16;
+// numbers drawn past the end of a slot come from up here, still repeatable but
+// clear of every other slot's dimensions
+const OVERFLOW_DIMENSIONS: u64 = 1 << 40;
+
+// the parts of a path vertex that draw random numbers
+#[derive(Clone, Copy)]
+pub enum Slot {
+    // stochastic alpha tests while finding the hit
+    Intersection,
+    // next event estimation towards the background, then the lights
+    Background,
+    // picking a lobe and a direction
+    Scatter,
+    // picking the channel of a walk into the surface's medium
+    Interior,
+    // distance to the next interaction in a medium
+    Distance,
+    Phase,
+    Roulette,
+}
+
+impl Slot {
+    // offset into the vertex's block and how many dimensions it has
+    fn range(self) -> (u64, u64) {
+        match self {
+            Slot::Intersection => (0, 2),
+            Slot::Background => (2, 4),
+            Slot::Scatter => (6, 5),
+            Slot::Interior => (11, 1),
+            Slot::Distance => (12, 1),
+            Slot::Phase => (13, 2),
+            Slot::Roulette => (15, 1),
+        }
+    }
+}
+
+struct Active {
+    sampler: Box<dyn Sampler>,
+    // random_float falls back to plain random numbers until a sample is started
+    started: bool,
+    vertices: u64,
+    vertex_start: u64,
+    dimension: u64,
+    slot_end: u64,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Active>> = const { RefCell::new(None) };
+}
+
+// installs the sampler random_float draws from on this thread, None goes back to plain random
+pub fn install(sampler: Option<Box<dyn Sampler>>) {
+    ACTIVE.with(|active| {
+        *active.borrow_mut() = sampler.map(|sampler| Active {
+            sampler,
+            started: false,
+            vertices: 0,
+            vertex_start: 0,
+            dimension: 0,
+            slot_end: 0,
+        })
+    });
+}
+
+// starts the camera's dimensions of a new sample
+pub fn start_sample(sample: SampleIndex) {
+    ACTIVE.with(|active| {
+        if let Some(active) = active.borrow_mut().as_mut() {
+            active.sampler.start_sample(sample);
+            active.started = true;
+            active.vertices = 0;
+            active.vertex_start = 0;
+            active.dimension = 0;
+            active.slot_end = CAMERA_DIMENSIONS;
+        }
+    });
+}
+
+// moves on to the next vertex of the path, its slots are picked with start_slot
+pub fn start_vertex() {
+    ACTIVE.with(|active| {
+        if let Some(active) = active.borrow_mut().as_mut() {
+            active.vertex_start = CAMERA_DIMENSIONS + active.vertices * VERTEX_DIMENSIONS;
+            active.vertices += 1;
+            active.dimension = active.vertex_start;
+            active.slot_end = active.dimension;
+        }
+    });
+}
+
+pub fn start_slot(slot: Slot) {
+    ACTIVE.with(|active| {
+        if let Some(active) = active.borrow_mut().as_mut() {
+            let (offset, size) = slot.range();
+            active.dimension = active.vertex_start + offset;
+            active.slot_end = active.dimension + size;
+        }
+    });
+}
+
+pub(super) fn next_1d() -> Option<f64> {
+    ACTIVE.with(|active| {
+        let mut active = active.borrow_mut();
+        let active = active.as_mut().filter(|active| active.started)?;
+
+        let dimension = if active.dimension < active.slot_end {
+            active.dimension
+        } else {
+            OVERFLOW_DIMENSIONS + (active.slot_end << 16) + (active.dimension - active.slot_end)
+        };
+        active.dimension += 1;
+        Some(active.sampler.get_1d(dimension))
+    })
+}
+
+// uniform random numbers, hashed from the sample so renders are repeatable
+#[derive(Clone, Copy, Default)]
+pub struct Independent {
+    key: Option<u64>,
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, sample: SampleIndex) {
+        self.key = Some(hash(&[
+            sample.seed,
+            sample.x as u64,
+            sample.y as u64,
+            sample.index as u64,
+        ]));
+    }
+
+    fn get_1d(&mut self, dimension: u64) -> f64 {
+        match self.key {
+            Some(key) => to_float(hash(&[key, dimension])),
+            None => unsampled_float(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "independent"
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+// jittered strata in every dimension, with the strata shuffled independently per
+// dimension so the dimensions don't line up with each other (a latin hypercube)
+#[derive(Clone, Copy, Default)]
+pub struct Stratified {
+    sample: Option<SampleIndex>,
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, sample: SampleIndex) {
+        self.sample = Some(sample);
+    }
+
+    fn get_1d(&mut self, dimension: u64) -> f64 {
+        let Some(sample) = self.sample else {
+            return unsampled_float();
+        };
+        let key = hash(&[sample.seed, sample.x as u64, sample.y as u64, dimension]);
+
+        let count = sample.count.max(1);
+        let stratum = permute(sample.index % count, count, key as u32);
+        let jitter = to_float(hash(&[key, sample.index as u64]));
+        // a jitter just under 1 in the last stratum would round up to 1
+        ((stratum as f64 + jitter) / count as f64).min(1.0 - f64::EPSILON / 2.0)
+    }
+
+    fn name(&self) -> &'static str {
+        "stratified"
+    }
+
+    fn uses_count(&self) -> bool {
+        true
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// the radical inverse in a different prime base per dimension, owen scrambled per
+// pixel so the larger bases don't line up with each other. past the prime table it
+// falls back to hashed random numbers
+#[derive(Clone, Copy, Default)]
+pub struct Halton {
+    sample: Option<SampleIndex>,
+}
+
+impl Sampler for Halton {
+    fn start_sample(&mut self, sample: SampleIndex) {
+        self.sample = Some(sample);
+    }
+
+    fn get_1d(&mut self, dimension: u64) -> f64 {
+        let Some(sample) = self.sample else {
+            return unsampled_float();
+        };
+
+        let key = hash(&[sample.seed, sample.x as u64, sample.y as u64, dimension]);
+        if dimension as usize >= PRIMES.len() {
+            return to_float(hash(&[key, sample.index as u64]));
+        }
+
+        owen_radical_inverse(sample.index, PRIMES[dimension as usize], key)
+    }
+
+    fn name(&self) -> &'static str {
+        "halton"
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+// Burley, "Practical Hash-based Owen Scrambling". every pair of dimensions is its
+// own shuffled and scrambled copy of the first two sobol dimensions, which pads
+// out to as many dimensions as a path needs
+#[derive(Clone, Copy, Default)]
+pub struct Sobol {
+    sample: Option<SampleIndex>,
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, sample: SampleIndex) {
+        self.sample = Some(sample);
+    }
+
+    fn get_1d(&mut self, dimension: u64) -> f64 {
+        let Some(sample) = self.sample else {
+            return unsampled_float();
+        };
+
+        let pixel_seed = hash(&[sample.seed, sample.x as u64, sample.y as u64]);
+        owen_sobol(sample.index, dimension, pixel_seed)
+    }
+
+    fn name(&self) -> &'static str {
+        "sobol"
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+// every pixel walks the same owen-scrambled sobol sequence, shifted by interleaved
+// gradient noise (Jimenez 2014) so the error left over is spread like blue noise
+// across the image instead of white noise
+#[derive(Clone, Copy, Default)]
+pub struct BlueNoise {
+    sample: Option<SampleIndex>,
+}
+
+impl Sampler for BlueNoise {
+    fn start_sample(&mut self, sample: SampleIndex) {
+        self.sample = Some(sample);
+    }
+
+    fn get_1d(&mut self, dimension: u64) -> f64 {
+        let Some(sample) = self.sample else {
+            return unsampled_float();
+        };
+
+        let value = owen_sobol(sample.index, dimension, hash(&[sample.seed]));
+        // each dimension reads the noise at a different offset so they stay uncorrelated
+        let offset = 5.588238 * dimension as f64;
+        let noise = interleaved_gradient_noise(sample.x as f64 + offset, sample.y as f64 + offset);
+        (value + noise).fract()
+    }
+
+    fn name(&self) -> &'static str {
+        "blue noise"
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+fn owen_sobol(index: u32, dimension: u64, seed: u64) -> f64 {
+    let pair_seed = hash(&[seed, dimension / 2]) as u32;
+    let shuffled = nested_uniform_scramble(index, pair_seed);
+
+    let value = if dimension.is_multiple_of(2) {
+        shuffled.reverse_bits()
+    } else {
+        sobol_second_dimension(shuffled)
+    };
+    let scrambled = nested_uniform_scramble(value, hash(&[seed, dimension]) as u32);
+    scrambled as f64 / 4294967296.0
+}
+
+// the pascal matrix generator, the first dimension is just the bit reversed index
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+pub(super) fn radical_inverse(mut index: u32, base: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+    result
+}
+
+// every digit goes through a permutation picked by the digits above it, carried on
+// past the index's own digits until they're below double precision
+fn owen_radical_inverse(mut index: u32, base: u32, key: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+    let mut prefix = 0u64;
+    while factor > 1e-16 {
+        let digit = index % base;
+        let permuted = permute(digit, base, hash(&[key, prefix]) as u32);
+        result += permuted as f64 * factor;
+        prefix = prefix
+            .wrapping_mul(base as u64)
+            .wrapping_add(digit as u64 + 1);
+        index /= base;
+        factor *= inverse_base;
+    }
+    result.min(1.0 - f64::EPSILON / 2.0)
+}
+
+fn interleaved_gradient_noise(x: f64, y: f64) -> f64 {
+    (52.9829189 * (0.06711056 * x + 0.00583715 * y).fract()).fract()
+}
+
+// Kensler, "Correlated Multi-Jittered Sampling". a random permutation of 0..count
+// chosen by the key, without building a table
+fn permute(mut i: u32, count: u32, key: u32) -> u32 {
+    let mut mask = count.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        i ^= key;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= key >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= key >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= key >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | key >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < count {
+            break;
+        }
+    }
+    (i.wrapping_add(key)) % count
+}
+
+// splitmix64's finalizer
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x2545_f491_4f6c_dd1d, |h, &v| mix(h ^ mix(v)))
+}
+
+// top 53 bits, so the result is always below 1
+fn to_float(bits: u64) -> f64 {
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(index: u32, count: u32) -> SampleIndex {
+        SampleIndex {
+            x: 3,
+            y: 7,
+            index,
+            count,
+            seed: 11,
+        }
+    }
+
+    // the values of one dimension over every sample of a pixel
+    fn dimension_values(sampler: &mut dyn Sampler, count: u32, dimension: u64) -> Vec<f64> {
+        (0..count)
+            .map(|index| {
+                sampler.start_sample(sample(index, count));
+                sampler.get_1d(dimension)
+            })
+            .collect()
+    }
+
+    // how many of the values land in each of count equal strata
+    fn strata(values: &[f64], count: usize) -> Vec<usize> {
+        let mut strata = vec![0; count];
+        for value in values {
+            strata[(value * count as f64) as usize] += 1;
+        }
+        strata
+    }
+
+    #[test]
+    fn samples_stay_in_the_unit_interval() {
+        let samplers: [Box<dyn Sampler>; 5] = [
+            Box::new(Independent::default()),
+            Box::new(Stratified::default()),
+            Box::new(Halton::default()),
+            Box::new(Sobol::default()),
+            Box::new(BlueNoise::default()),
+        ];
+        let dimensions = (0..80).chain([OVERFLOW_DIMENSIONS, OVERFLOW_DIMENSIONS + 1]);
+
+        for mut sampler in samplers {
+            for dimension in dimensions.clone() {
+                for x in 0..4 {
+                    for index in 0..64 {
+                        sampler.start_sample(SampleIndex {
+                            x,
+                            ..sample(index, 64)
+                        });
+                        let value = sampler.get_1d(dimension);
+                        assert!(
+                            (0.0..1.0).contains(&value),
+                            "{} gave {value}",
+                            sampler.name()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_covers_every_stratum_once() {
+        let mut sampler = Stratified::default();
+        for count in [1, 5, 16, 37] {
+            for dimension in [0, 1, 9, 100] {
+                let values = dimension_values(&mut sampler, count, dimension);
+                assert!(strata(&values, count as usize).iter().all(|&n| n == 1));
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_covers_every_stratum_once() {
+        let mut sampler = Sobol::default();
+        for count in [1, 2, 16, 256] {
+            for dimension in [0, 1, 2, 7, 30] {
+                let values = dimension_values(&mut sampler, count, dimension);
+                assert!(strata(&values, count as usize).iter().all(|&n| n == 1));
+            }
+        }
+
+        // pairs of dimensions are a (0, m, 2) net, every elementary interval of
+        // area 1 / count holds one sample
+        let count = 64;
+        let xs = dimension_values(&mut sampler, count, 4);
+        let ys = dimension_values(&mut sampler, count, 5);
+        for (columns, rows) in [(64, 1), (16, 4), (8, 8), (2, 32)] {
+            let mut cells = vec![0; columns * rows];
+            for (x, y) in xs.iter().zip(&ys) {
+                let cell = (y * rows as f64) as usize * columns + (x * columns as f64) as usize;
+                cells[cell] += 1;
+            }
+            assert!(cells.iter().all(|&n| n == 1), "{columns} by {rows}");
+        }
+    }
+
+    #[test]
+    fn halton_is_stratified_in_its_base() {
+        let mut sampler = Halton::default();
+        for (dimension, count) in [(0, 32), (1, 27), (2, 25)] {
+            let values = dimension_values(&mut sampler, count, dimension);
+            assert!(strata(&values, count as usize).iter().all(|&n| n == 1));
+        }
+    }
+}
@@ -1,10 +1,10 @@
 use crate::raytracing::{
     hittable::{HitRecord, Hittable},
     ray::Ray,
-    vec3::{dot, Vec3},
+    vec3::{cross, dot, Point, Vec3},
 };
 
-use super::{interval::Interval, materials::Material};
+use super::{interval::Interval, materials::Material, onb::Onb, PI};
 
 pub struct Sphere {
     center: Vec3,
@@ -20,6 +20,24 @@ impl Sphere {
             material,
         }
     }
+
+    // p is a point on the unit sphere, u runs around the y axis from -x and v from -y to +y
+    fn get_sphere_uv(p: Point) -> (f64, f64) {
+        let theta = (-p.y).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // direction of increasing u, which degenerates at the poles
+    fn get_sphere_tangent(p: Point) -> Vec3 {
+        let tangent = Vec3::new(p.z, 0.0, -p.x);
+        if tangent.near_zero() {
+            Onb::new(p).u
+        } else {
+            tangent.unit_vector()
+        }
+    }
 }
 
 impl Hittable for Sphere {
@@ -36,21 +54,30 @@ impl Hittable for Sphere {
 
         let sqrtd = discriminant.sqrt();
 
-        let mut root = (h - sqrtd) / a;
-
-        if root <= ray_t.min || ray_t.max <= root {
-            root = (h + sqrtd) / a;
+        // the far root is still a candidate when the near one is cut out
+        for root in [(h - sqrtd) / a, (h + sqrtd) / a] {
             if root <= ray_t.min || ray_t.max <= root {
-                return false;
+                continue;
+            }
+
+            rec.t = root;
+            rec.point = ray.at(rec.t);
+            let outward_normal = (rec.point - self.center) / self.radius;
+            rec.set_face_normal(ray, outward_normal);
+            (rec.u, rec.v) = Self::get_sphere_uv(outward_normal);
+            rec.tangent = Self::get_sphere_tangent(outward_normal);
+            rec.bitangent = cross(outward_normal, rec.tangent);
+
+            if self.material.alpha_test(rec) {
+                rec.material = self.material.clone();
+                return true;
             }
         }
 
-        rec.t = root;
-        rec.point = ray.at(rec.t);
-        let outward_normal = (rec.point - self.center) / self.radius;
-        rec.set_face_normal(ray, outward_normal);
-        rec.material = self.material.clone();
+        false
+    }
 
-        true
+    fn center(&self) -> Option<Point> {
+        Some(self.center)
     }
 }
@@ -0,0 +1,319 @@
+use super::{
+    background::{Background, BackgroundSample},
+    color::Color,
+    degrees_to_radians,
+    onb::Onb,
+    random_float,
+    vec3::{dot, Vec3},
+    PI,
+};
+
+// Preetham, Shirley & Smits, "A Practical Analytic Model for Daylight"
+// https://courses.cs.duke.edu/cps124/spring08/assign/07_papers/p91-preetham.pdf
+
+// the model outputs luminance in kcd/m^2, this brings a clear midday sky to roughly 1.0
+const SKY_SCALE: f64 = 0.08;
+
+// direct sunlight on a clear day is around 100 klux before atmospheric extinction
+const SUN_ILLUMINANCE: f64 = 100.0;
+
+// solar disc as seen from the ground, uniformly sampled over its cone
+pub struct Sun {
+    pub direction: Vec3,
+    pub angular_radius: f64,
+    pub radiance: Color,
+    cos_angular_radius: f64,
+}
+
+impl Sun {
+    // angular_radius is in degrees, irradiance is measured perpendicular to the sun
+    pub fn new(direction: Vec3, angular_radius: f64, irradiance: Color) -> Self {
+        let cos_angular_radius = degrees_to_radians(angular_radius).cos();
+        let solid_angle = 2.0 * PI * (1.0 - cos_angular_radius);
+
+        Self {
+            direction: direction.unit_vector(),
+            angular_radius,
+            radiance: irradiance * (1.0 / solid_angle),
+            cos_angular_radius,
+        }
+    }
+
+    pub fn contains(&self, direction: Vec3) -> bool {
+        dot(direction.unit_vector(), self.direction) >= self.cos_angular_radius
+    }
+
+    pub fn sample_direction(&self) -> Vec3 {
+        let cos_theta = 1.0 - random_float() * (1.0 - self.cos_angular_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_float();
+
+        Onb::new(self.direction).transform(Vec3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ))
+    }
+
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        if self.contains(direction) {
+            1.0 / (2.0 * PI * (1.0 - self.cos_angular_radius))
+        } else {
+            0.0
+        }
+    }
+}
+
+pub struct PhysicalSky {
+    pub sun: Sun,
+    pub turbidity: f64,
+    pub intensity: f64,
+    theta_sun: f64,
+    perez_y: [f64; 5],
+    perez_x: [f64; 5],
+    perez_yy: [f64; 5],
+    zenith: (f64, f64, f64),
+}
+
+impl PhysicalSky {
+    // elevation and azimuth in degrees, azimuth 0 looks down -z and 90 down +x
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64) -> Self {
+        let elevation = degrees_to_radians(sun_elevation.clamp(-90.0, 90.0));
+        let azimuth = degrees_to_radians(sun_azimuth);
+        let direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+
+        // the model is only defined with the sun above the horizon
+        let theta_sun = (PI / 2.0 - elevation).min(PI / 2.0);
+        let t = turbidity.clamp(1.7, 10.0);
+
+        let perez_yy = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let perez_x = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let perez_y = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+
+        let th = theta_sun;
+        let th2 = th * th;
+        let th3 = th2 * th;
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let sun_irradiance = if sun_elevation > 0.0 {
+            sun_transmittance(theta_sun, t) * (SUN_ILLUMINANCE * SKY_SCALE)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        };
+
+        Self {
+            sun: Sun::new(direction, 0.266, sun_irradiance),
+            turbidity: t,
+            intensity: 1.0,
+            theta_sun,
+            perez_y,
+            perez_x,
+            perez_yy,
+            zenith: (zenith_x, zenith_y, zenith_luminance),
+        }
+    }
+
+    // a larger radius gives softer shadows, the real sun is about 0.266 degrees
+    pub fn with_sun_angular_radius(mut self, angular_radius: f64) -> Self {
+        let irradiance = self.sun.radiance
+            * (2.0 * PI * (1.0 - degrees_to_radians(self.sun.angular_radius).cos()));
+        self.sun = Sun::new(self.sun.direction, angular_radius, irradiance);
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    fn perez(coefficients: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = *coefficients;
+        (1.0 + a * (b / theta.cos()).exp())
+            * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+    }
+
+    fn sky_color(&self, direction: Vec3) -> Color {
+        let d = direction.unit_vector();
+
+        // keep theta just above the horizon, the ground is left to the scene
+        let theta = d.y.clamp(0.001, 1.0).acos();
+        let gamma = dot(d, self.sun.direction).clamp(-1.0, 1.0).acos();
+
+        let (zenith_x, zenith_y, zenith_luminance) = self.zenith;
+        let x = zenith_x * Self::perez(&self.perez_x, theta, gamma)
+            / Self::perez(&self.perez_x, 0.0, self.theta_sun);
+        let y = zenith_y * Self::perez(&self.perez_y, theta, gamma)
+            / Self::perez(&self.perez_y, 0.0, self.theta_sun);
+        let luminance = zenith_luminance * Self::perez(&self.perez_yy, theta, gamma)
+            / Self::perez(&self.perez_yy, 0.0, self.theta_sun);
+
+        if y <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let big_x = x / y * luminance;
+        let big_z = (1.0 - x - y) / y * luminance;
+        let color = Color::from_xyz(big_x, luminance, big_z) * SKY_SCALE;
+
+        Color::new(color.r.max(0.0), color.g.max(0.0), color.b.max(0.0))
+    }
+}
+
+// rayleigh and aerosol extinction along the path to the sun, from the paper's appendix
+fn sun_transmittance(theta_sun: f64, turbidity: f64) -> Color {
+    let beta = 0.04608 * turbidity - 0.04586;
+    let theta_degrees = theta_sun.to_degrees();
+    let air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+
+    // representative wavelengths for each channel, in micrometers
+    let transmittance = |lambda: f64| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-air_mass * (rayleigh + aerosol)).exp()
+    };
+
+    Color::new(
+        transmittance(0.65),
+        transmittance(0.57),
+        transmittance(0.475),
+    )
+}
+
+impl Background for PhysicalSky {
+    fn color(&self, direction: Vec3) -> Color {
+        let mut color = self.sky_color(direction);
+        if self.sun.contains(direction) {
+            color += self.sun.radiance;
+        }
+        color * self.intensity
+    }
+
+    fn sample(&self) -> Option<BackgroundSample> {
+        if self.sun.radiance.near_zero() {
+            return None;
+        }
+
+        let direction = self.sun.sample_direction();
+        Some(BackgroundSample {
+            direction,
+            radiance: self.color(direction),
+            pdf: self.sun.pdf(direction),
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        if self.sun.radiance.near_zero() {
+            return 0.0;
+        }
+        self.sun.pdf(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::seed_random;
+
+    fn sun() -> Sun {
+        Sun::new(Vec3::new(0.3, 0.8, -0.5), 5.0, Color::new(2.0, 1.5, 1.0))
+    }
+
+    #[test]
+    fn sun_pdf_integrates_to_one() {
+        let sun = sun();
+        let frame = Onb::new(sun.direction);
+        let radius = degrees_to_radians(sun.angular_radius);
+
+        // midpoint rule over a cap twice the size of the disc, in the sun's own frame
+        let (steps, turns) = (2000, 64);
+        let d_theta = 2.0 * radius / steps as f64;
+        let d_phi = 2.0 * PI / turns as f64;
+        let mut total = 0.0;
+        for i in 0..steps {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..turns {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let direction = frame.transform(Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ));
+                total += sun.pdf(direction) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-3, "{total}");
+    }
+
+    #[test]
+    fn sun_samples_fill_the_disc_evenly() {
+        seed_random(1);
+        let sun = sun();
+        let cos_max = degrees_to_radians(sun.angular_radius).cos();
+
+        let samples = 100_000;
+        let mut mean_cos = 0.0;
+        for _ in 0..samples {
+            let direction = sun.sample_direction();
+            assert!(sun.contains(direction));
+            assert!(sun.pdf(direction) > 0.0);
+            mean_cos += dot(direction.unit_vector(), sun.direction);
+        }
+        // uniform over the cap puts the mean cosine halfway between its ends
+        mean_cos /= samples as f64;
+        assert!((mean_cos - (1.0 + cos_max) / 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sun_radiance_adds_up_to_its_irradiance() {
+        let sun = sun();
+        let cos_max = degrees_to_radians(sun.angular_radius).cos();
+        let irradiance = sun.radiance * (2.0 * PI * (1.0 - cos_max));
+        assert!((irradiance.r - 2.0).abs() < 1e-9 && (irradiance.b - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sky_sample_agrees_with_pdf() {
+        seed_random(2);
+        let sky = PhysicalSky::new(40.0, 120.0, 3.0);
+        for _ in 0..1000 {
+            let sample = sky.sample().unwrap();
+            assert!((sample.pdf - sky.pdf(sample.direction)).abs() < 1e-9);
+            assert!(sample.radiance.luminance() > 0.0);
+        }
+
+        // a sun below the horizon gives no light to sample
+        let night = PhysicalSky::new(-10.0, 0.0, 3.0);
+        assert!(night.sample().is_none());
+        assert_eq!(night.pdf(night.sun.direction), 0.0);
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    ops::{Add, AddAssign, Mul, MulAssign},
+    sync::OnceLock,
+};
+
+use super::color::Color;
+
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// integral of the cie y matching function over the visible range
+const CIE_Y_INTEGRAL: f64 = 106.856895;
+
+pub const WAVELENGTH_SAMPLES: usize = 4;
+
+// a primary wavelength plus evenly rotated companions (the stratification from
+// Wilkie et al. "Hero Wavelength Spectral Sampling"). there is no mis between them,
+// a dispersive bounce keeps only the primary and goes on as a single wavelength path
+#[derive(Clone, Copy)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; WAVELENGTH_SAMPLES],
+    pub pdf: [f64; WAVELENGTH_SAMPLES],
+}
+
+impl Default for SampledWavelengths {
+    // a single fixed wavelength for rays that don't need a real sample, like shadow rays
+    fn default() -> Self {
+        Self {
+            lambda: [550.0; WAVELENGTH_SAMPLES],
+            pdf: [1.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+impl SampledWavelengths {
+    // importance samples the visible range, from pbrt's SampleVisibleWavelengths
+    pub fn sample_visible(u: f64) -> Self {
+        let mut lambda = [0.0; WAVELENGTH_SAMPLES];
+        let mut pdf = [0.0; WAVELENGTH_SAMPLES];
+
+        for i in 0..WAVELENGTH_SAMPLES {
+            let up = (u + i as f64 / WAVELENGTH_SAMPLES as f64).fract();
+            lambda[i] = sample_visible_wavelength(up);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+
+        Self { lambda, pdf }
+    }
+
+    pub fn primary(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    // once a path depends on wavelength only the primary can carry on. a smooth
+    // interface refracts each wavelength into its own direction, so the companions
+    // have zero pdf for the one that was taken and can't be weighted in. the primary's
+    // pdf becomes that of a single wavelength sample, which is what rgb_weight needs
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= WAVELENGTH_SAMPLES as f64;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf.iter().skip(1).all(|&pdf| pdf == 0.0)
+    }
+
+    // rgb weight of the surviving wavelengths, white balanced so it averages to
+    // (1, 1, 1) over the wavelength distribution
+    pub fn rgb_weight(&self) -> Color {
+        let mut weight = Color::new(0.0, 0.0, 0.0);
+        for i in 0..WAVELENGTH_SAMPLES {
+            if self.pdf[i] > 0.0 {
+                weight += wavelength_to_rgb(self.lambda[i])
+                    * (1.0 / (self.pdf[i] * WAVELENGTH_SAMPLES as f64));
+            }
+        }
+        weight
+    }
+}
+
+fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.0039398042 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+fn piecewise_gaussian(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma = if x < mu { sigma_low } else { sigma_high };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// multi-lobe fit to the cie 1931 observer, Wyman, Sloan & Shirley
+// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions"
+pub fn cie_xyz(lambda: f64) -> (f64, f64, f64) {
+    let x = 1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8);
+
+    (x, y, z)
+}
+
+// linear srgb of an equal energy spectrum, used to white balance wavelength weights
+fn equal_energy_white() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+
+    *WHITE.get_or_init(|| {
+        let mut white = Color::new(0.0, 0.0, 0.0);
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            let (x, y, z) = cie_xyz(lambda);
+            white += Color::from_xyz(x, y, z) * (1.0 / CIE_Y_INTEGRAL);
+            lambda += 1.0;
+        }
+        white
+    })
+}
+
+// contribution of a unit spike at lambda to linear srgb
+pub fn wavelength_to_rgb(lambda: f64) -> Color {
+    let (x, y, z) = cie_xyz(lambda);
+    let rgb = Color::from_xyz(x, y, z) * (1.0 / CIE_Y_INTEGRAL);
+    let white = equal_energy_white();
+
+    Color::new(rgb.r / white.r, rgb.g / white.g, rgb.b / white.b)
+}
+
+// radiance or reflectance at each of the sampled wavelengths
+#[derive(Clone, Copy)]
+pub struct SampledSpectrum {
+    pub values: [f64; WAVELENGTH_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(values: [f64; WAVELENGTH_SAMPLES]) -> Self {
+        Self { values }
+    }
+
+    pub fn splat(value: f64) -> Self {
+        Self::new([value; WAVELENGTH_SAMPLES])
+    }
+
+    // keeps only the primary wavelength, scaled up to stand in for all of them. the
+    // path is converted with the camera ray's wavelengths, whose pdfs still average
+    // over every sample, so terminate_secondary's pdf division never reaches to_rgb
+    pub fn primary_mask() -> Self {
+        let mut values = [0.0; WAVELENGTH_SAMPLES];
+        values[0] = WAVELENGTH_SAMPLES as f64;
+        Self::new(values)
+    }
+
+    // upsamples an rgb reflectance, Smits "An RGB to Spectrum Conversion for Reflectances"
+    pub fn from_reflectance(color: Color, wavelengths: &SampledWavelengths) -> Self {
+        Self::new(wavelengths.lambda.map(|lambda| smits(color, lambda)))
+    }
+
+    // upsamples an rgb emission as a tint on top of the illuminant's spectrum
+    pub fn from_illuminant(
+        color: Color,
+        illuminant: &Illuminant,
+        wavelengths: &SampledWavelengths,
+    ) -> Self {
+        Self::new(
+            wavelengths
+                .lambda
+                .map(|lambda| smits(color, lambda) * illuminant.spd(lambda)),
+        )
+    }
+
+    // monte carlo estimate of the spectrum's xyz, white balanced to d65 in linear srgb
+    pub fn to_rgb(&self, wavelengths: &SampledWavelengths) -> Color {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for i in 0..WAVELENGTH_SAMPLES {
+            if wavelengths.pdf[i] == 0.0 {
+                continue;
+            }
+            let (cx, cy, cz) = cie_xyz(wavelengths.lambda[i]);
+            let weight = self.values[i] / (wavelengths.pdf[i] * WAVELENGTH_SAMPLES as f64);
+            x += cx * weight;
+            y += cy * weight;
+            z += cz * weight;
+        }
+
+        let rgb = Color::from_xyz(x, y, z) * (1.0 / CIE_Y_INTEGRAL);
+        let white = Illuminant::d65().unbalanced_rgb;
+        Color::new(rgb.r / white.r, rgb.g / white.g, rgb.b / white.b)
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut values = self.values;
+        for (value, o) in values.iter_mut().zip(other.values) {
+            *value += o;
+        }
+        Self::new(values)
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs
+    }
+}
+
+impl Mul<SampledSpectrum> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut values = self.values;
+        for (value, o) in values.iter_mut().zip(other.values) {
+            *value *= o;
+        }
+        Self::new(values)
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, f: f64) -> Self {
+        Self::new(self.values.map(|v| v * f))
+    }
+}
+
+impl MulAssign<SampledSpectrum> for SampledSpectrum {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs
+    }
+}
+
+// Smits' basis spectra, sampled at the centres of ten bins between 380 and 720nm
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+fn smits_basis(basis: &[f64; 10], lambda: f64) -> f64 {
+    const START: f64 = 380.0;
+    const BIN: f64 = 34.0;
+
+    let position = ((lambda - START) / BIN - 0.5).clamp(0.0, 9.0);
+    let i = (position as usize).min(8);
+    let t = position - i as f64;
+    basis[i] * (1.0 - t) + basis[i + 1] * t
+}
+
+fn smits(color: Color, lambda: f64) -> f64 {
+    let r = color.r.max(0.0);
+    let g = color.g.max(0.0);
+    let b = color.b.max(0.0);
+    let basis = |spectrum: &[f64; 10]| smits_basis(spectrum, lambda);
+
+    let value = if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    };
+
+    value.max(0.0)
+}
+
+// cie standard illuminant d65 from 380 to 780nm in 10nm steps
+const D65: [f64; 41] = [
+    49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008, 117.812, 114.861,
+    115.923, 108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046, 100.0, 96.3342, 95.788,
+    88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778, 78.2842,
+    69.7213, 71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054, 63.3828,
+];
+
+#[derive(Clone, Copy)]
+enum IlluminantKind {
+    D65,
+    Blackbody(f64),
+}
+
+// emission spectrum of a light source, normalized to unit luminance
+#[derive(Clone, Copy)]
+pub struct Illuminant {
+    kind: IlluminantKind,
+    scale: f64,
+    unbalanced_rgb: Color,
+}
+
+impl Illuminant {
+    pub fn d65() -> Self {
+        static D65_ILLUMINANT: OnceLock<Illuminant> = OnceLock::new();
+        *D65_ILLUMINANT.get_or_init(|| Self::normalized(IlluminantKind::D65))
+    }
+
+    // cie illuminant a, an incandescent tungsten filament
+    pub fn tungsten() -> Self {
+        Self::blackbody(2856.0)
+    }
+
+    // temperature in kelvin
+    pub fn blackbody(temperature: f64) -> Self {
+        Self::normalized(IlluminantKind::Blackbody(temperature))
+    }
+
+    fn normalized(kind: IlluminantKind) -> Self {
+        let mut illuminant = Self {
+            kind,
+            scale: 1.0,
+            unbalanced_rgb: Color::new(1.0, 1.0, 1.0),
+        };
+
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            let spd = illuminant.spd(lambda);
+            let (cx, cy, cz) = cie_xyz(lambda);
+            x += spd * cx;
+            y += spd * cy;
+            z += spd * cz;
+            lambda += 1.0;
+        }
+
+        illuminant.scale = CIE_Y_INTEGRAL / y;
+        illuminant.unbalanced_rgb = Color::from_xyz(x / y, 1.0, z / y);
+        illuminant
+    }
+
+    pub fn spd(&self, lambda: f64) -> f64 {
+        let value = match self.kind {
+            IlluminantKind::D65 => {
+                let position = ((lambda - 380.0) / 10.0).clamp(0.0, 40.0);
+                let i = (position as usize).min(39);
+                let t = position - i as f64;
+                D65[i] * (1.0 - t) + D65[i + 1] * t
+            }
+            IlluminantKind::Blackbody(temperature) => planck(lambda, temperature),
+        };
+        value * self.scale
+    }
+
+    // the illuminant's colour in linear srgb, with d65 as white
+    pub fn rgb(&self) -> Color {
+        let white = Self::d65().unbalanced_rgb;
+        Color::new(
+            self.unbalanced_rgb.r / white.r,
+            self.unbalanced_rgb.g / white.g,
+            self.unbalanced_rgb.b / white.b,
+        )
+    }
+}
+
+impl Default for Illuminant {
+    fn default() -> Self {
+        Self::d65()
+    }
+}
+
+// spectral radiance of a blackbody, wavelength in nanometers
+fn planck(lambda: f64, temperature: f64) -> f64 {
+    const C: f64 = 299792458.0;
+    const H: f64 = 6.62606957e-34;
+    const KB: f64 = 1.3806488e-23;
+
+    let l = lambda * 1e-9;
+    (2.0 * H * C * C) / (l.powi(5) * ((H * C) / (l * KB * temperature)).exp_m1())
+}
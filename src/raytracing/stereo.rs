@@ -0,0 +1,241 @@
+use std::mem;
+
+use super::{
+    camera::{Camera, Focus},
+    color::Color,
+    hittable::HittableList,
+    image::Image,
+    vec3::{cross, Vec3},
+};
+
+#[derive(Clone, Copy)]
+pub enum Convergence {
+    // both eyes rotate to look at the convergence point, simple but adds vertical parallax
+    ToeIn,
+    // both eyes look straight ahead and shift their image planes to converge
+    OffAxis,
+}
+
+#[derive(Clone, Copy)]
+pub enum StereoLayout {
+    // left eye on the left
+    SideBySide,
+    // left eye on top
+    TopBottom,
+    // red from the left eye, green and blue from the right
+    Anaglyph,
+}
+
+// renders the camera twice, once per eye. focus is resolved once from the middle of
+// the rig, the eyes converge there and share the seed so their noise matches
+pub struct StereoRig {
+    pub camera: Camera,
+    pub interocular_distance: f64,
+    pub convergence: Convergence,
+    pub layout: StereoLayout,
+}
+
+impl StereoRig {
+    pub fn new(camera: Camera, interocular_distance: f64) -> Self {
+        Self {
+            camera,
+            interocular_distance,
+            convergence: Convergence::OffAxis,
+            layout: StereoLayout::SideBySide,
+        }
+    }
+
+    pub fn with_convergence(mut self, convergence: Convergence) -> Self {
+        self.convergence = convergence;
+        self
+    }
+
+    pub fn with_layout(mut self, layout: StereoLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn render(&mut self, world: HittableList) {
+        self.render_image(&world).write_ppm();
+    }
+
+    pub fn render_image(&mut self, world: &HittableList) -> Image {
+        let focus = self.fix_focus(world);
+        // a checkpoint path holds one film, the right eye would overwrite the left
+        let checkpoint = self.camera.checkpoint.take();
+
+        let left = self.render_eye(world, -1.0);
+        let right = self.render_eye(world, 1.0);
+
+        self.camera.focus = focus;
+        self.camera.checkpoint = checkpoint;
+        let (width, height) = (left.width, left.height);
+
+        match self.layout {
+            StereoLayout::SideBySide => {
+                let mut image = Image::new(width * 2, height);
+                for y in 0..height {
+                    for x in 0..width {
+                        image.set(x, y, left.get(x, y));
+                        image.set(x + width, y, right.get(x, y));
+                    }
+                }
+                image
+            }
+            StereoLayout::TopBottom => {
+                let mut image = Image::new(width, height * 2);
+                for y in 0..height {
+                    for x in 0..width {
+                        image.set(x, y, left.get(x, y));
+                        image.set(x, y + height, right.get(x, y));
+                    }
+                }
+                image
+            }
+            StereoLayout::Anaglyph => {
+                let mut image = Image::new(width, height);
+                for (i, pixel) in image.pixels.iter_mut().enumerate() {
+                    *pixel = Color::new(left.pixels[i].r, right.pixels[i].g, right.pixels[i].b);
+                }
+                image
+            }
+        }
+    }
+
+    // resolves focus_dist from the centre lookfrom and lookat and leaves the camera on
+    // Focus::Manual, so neither eye re-resolves it from its own position. returns the
+    // camera's focus to put back afterwards
+    fn fix_focus(&mut self, world: &HittableList) -> Focus {
+        self.camera.initialize(world);
+        mem::replace(&mut self.camera.focus, Focus::Manual)
+    }
+
+    // lookfrom, lookat and lens_shift of an eye, -1 for the left and 1 for the right
+    fn eye_view(&self, eye: f64) -> (Vec3, Vec3, (f64, f64)) {
+        let (lookfrom, lookat, lens_shift) = (
+            self.camera.lookfrom,
+            self.camera.lookat,
+            self.camera.lens_shift,
+        );
+
+        let forward = (lookat - lookfrom).unit_vector();
+        let right = cross(forward, self.camera.vup).unit_vector();
+        let half_offset = eye * self.interocular_distance / 2.0;
+        let offset: Vec3 = right * half_offset;
+
+        match self.convergence {
+            Convergence::ToeIn => (
+                lookfrom + offset,
+                lookfrom + forward * self.camera.focus_dist,
+                lens_shift,
+            ),
+            Convergence::OffAxis => (
+                lookfrom + offset,
+                lookat + offset,
+                (lens_shift.0 - half_offset, lens_shift.1),
+            ),
+        }
+    }
+
+    fn render_eye(&mut self, world: &HittableList, eye: f64) -> Image {
+        let (lookfrom, lookat, lens_shift) = (
+            self.camera.lookfrom,
+            self.camera.lookat,
+            self.camera.lens_shift,
+        );
+
+        (
+            self.camera.lookfrom,
+            self.camera.lookat,
+            self.camera.lens_shift,
+        ) = self.eye_view(eye);
+        let image = self.camera.render_image(world);
+
+        self.camera.lookfrom = lookfrom;
+        self.camera.lookat = lookat;
+        self.camera.lens_shift = lens_shift;
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::{materials::Lambertian, shapes::Sphere, vec3::Point};
+
+    fn world() -> HittableList {
+        let mut world = HittableList::default();
+        world.add_named(
+            "ball",
+            Box::new(Sphere::new(
+                Point::new(0.5, 0.0, -5.0),
+                1.0,
+                Box::new(Lambertian::new(Color::new(0.7, 0.3, 0.3))),
+            )),
+        );
+        world
+    }
+
+    fn camera() -> Camera {
+        let mut camera = Camera::default();
+        camera.image_width = 12;
+        camera.aspect_ratio = 1.0;
+        camera.samples_per_pixel = 4;
+        camera.max_depth = 4;
+        camera.lookfrom = Point::new(0.0, 0.0, 0.0);
+        camera.lookat = Point::new(0.0, 0.0, -1.0);
+        camera.vup = Vec3::new(0.0, 1.0, 0.0);
+        camera.defocus_angle = 1.0;
+        camera
+    }
+
+    #[test]
+    fn eyes_without_separation_match_exactly() {
+        for convergence in [Convergence::ToeIn, Convergence::OffAxis] {
+            let mut rig = StereoRig::new(camera(), 0.0).with_convergence(convergence);
+            let image = rig.render_image(&world());
+
+            let width = image.width / 2;
+            for y in 0..image.height {
+                for x in 0..width {
+                    let (left, right) = (image.get(x, y), image.get(x + width, y));
+                    assert_eq!((left.r, left.g, left.b), (right.r, right.g, right.b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn toe_in_eyes_converge_on_the_same_point() {
+        for focus in [
+            Focus::LookAt,
+            Focus::Object("ball".to_string()),
+            Focus::Probe { x: 9, y: 6 },
+        ] {
+            let mut camera = camera();
+            camera.focus = focus;
+            let mut rig = StereoRig::new(camera, 0.5).with_convergence(Convergence::ToeIn);
+            rig.fix_focus(&world());
+
+            let (left_from, left_at, _) = rig.eye_view(-1.0);
+            let (right_from, right_at, _) = rig.eye_view(1.0);
+            assert!((left_at - right_at).length() < 1e-12);
+            assert!((left_from - right_from).length() > 0.4);
+            // the middle of the rig looks straight down -z
+            assert!(left_at.z <= -1.0 + 1e-12 && left_at.x.abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn toe_in_converges_at_the_resolved_distance() {
+        let mut camera = camera();
+        camera.focus = Focus::Object("ball".to_string());
+        let mut rig = StereoRig::new(camera, 0.5).with_convergence(Convergence::ToeIn);
+        rig.fix_focus(&world());
+
+        for eye in [-1.0, 1.0] {
+            let (_, lookat, _) = rig.eye_view(eye);
+            assert!((lookat - Point::new(0.0, 0.0, -5.0)).length() < 1e-12);
+        }
+    }
+}
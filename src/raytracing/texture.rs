@@ -0,0 +1,62 @@
+use std::{io, path::Path};
+
+use super::{color::Color, image::Image, interval::Interval, vec3::Point};
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: Point) -> Color;
+}
+
+pub struct SolidColor {
+    pub albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: Point) -> Color {
+        self.albedo
+    }
+}
+
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        Self { image }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, srgb: bool) -> io::Result<Self> {
+        Ok(Self::new(Image::load(path, srgb)?))
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point) -> Color {
+        if self.image.width == 0 || self.image.height == 0 {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        // v runs bottom to top, image rows run top to bottom
+        let unit = Interval { min: 0.0, max: 1.0 };
+        let u = unit.clamp(u);
+        let v = 1.0 - unit.clamp(v);
+
+        let i = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let j = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+        self.image.get(i, j)
+    }
+}
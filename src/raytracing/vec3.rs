@@ -3,7 +3,7 @@ use std::{
     ops::{Add, Div, Mul, Sub},
 };
 
-use super::{random_float, random_float_range};
+use super::{random_float, random_float_range, PI};
 
 #[derive(Copy, Clone)]
 pub struct Vec3 {
@@ -36,15 +36,13 @@ impl Vec3 {
         self / self.length()
     }
 
+    // uniform on the sphere from exactly two random numbers, a rejection loop would
+    // draw a different number of them every time and throw the sampler's dimensions off
     pub fn random_unit_vector() -> Vec3 {
-        loop {
-            let p = Vec3::random_range(-1.0, 1.0);
-            let lensq = p.length_squared();
-
-            if 1e-160 < lensq && lensq <= 1.0 {
-                return p / lensq.sqrt();
-            }
-        }
+        let z = 1.0 - 2.0 * random_float();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_float();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
 
     pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
@@ -161,14 +159,21 @@ pub fn cross(u: Vec3, v: Vec3) -> Vec3 {
 }
 
 pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(
-            random_float_range(-1.0, 1.0),
-            random_float_range(-1.0, 1.0),
-            0.0,
-        );
-        if p.length_squared() < 1.0 {
-            return p;
-        }
+    concentric_disk(random_float(), random_float())
+}
+
+// Shirley and Chiu's square to disk mapping, unlike rejection it always takes two
+// numbers so stratified samples stay stratified on the lens
+pub fn concentric_disk(u1: f64, u2: f64) -> Vec3 {
+    let (x, y) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if x == 0.0 && y == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
     }
+
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, PI / 4.0 * (y / x))
+    } else {
+        (y, PI / 2.0 - PI / 4.0 * (x / y))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }